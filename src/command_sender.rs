@@ -0,0 +1,61 @@
+use log::{error, info};
+use std::sync::{Arc, Mutex};
+
+/// The sender of a bot management command. Output is always logged, and is additionally captured
+/// for senders that want to show it somewhere else, such as commands run from Discord.
+#[derive(Debug, Clone)]
+pub(crate) struct CommandSender {
+    name: Arc<str>,
+    captured_output: Option<Arc<Mutex<Vec<String>>>>,
+}
+
+impl CommandSender {
+    pub(crate) fn console() -> Self {
+        Self::new("Console")
+    }
+
+    pub(crate) fn new(name: impl Into<Arc<str>>) -> Self {
+        Self {
+            name: name.into(),
+            captured_output: None,
+        }
+    }
+
+    pub(crate) fn capturing(name: impl Into<Arc<str>>) -> Self {
+        Self {
+            name: name.into(),
+            captured_output: Some(Arc::new(Mutex::new(Vec::new()))),
+        }
+    }
+
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn info(&self, message: impl Into<String>) {
+        let message = message.into();
+        info!("{}", message);
+        self.capture(message);
+    }
+
+    pub(crate) fn error(&self, message: impl Into<String>) {
+        let message = message.into();
+        error!("{}", message);
+        self.capture(message);
+    }
+
+    fn capture(&self, message: String) {
+        if let Some(captured_output) = &self.captured_output {
+            captured_output.lock().unwrap().push(message);
+        }
+    }
+
+    pub(crate) fn take_output(&self) -> String {
+        match &self.captured_output {
+            Some(captured_output) => {
+                std::mem::take(&mut *captured_output.lock().unwrap()).join("\n")
+            }
+            None => String::new(),
+        }
+    }
+}
@@ -1,11 +1,13 @@
+use crate::pterodactyl::console_rules::ConsoleRule;
 use crate::pterodactyl::{
-    PterodactylAllPerms, PterodactylChatBridge, PterodactylEmails, PterodactylServer,
-    PterodactylServerCategoryFilter,
+    default_categories, ops, PterodactylAllPerms, PterodactylCategoryInfo, PterodactylChatBridge,
+    PterodactylEmails, PterodactylPanel, PterodactylServer, PterodactylServerCategory,
+    PterodactylServerCategoryFilter, PterodactylTierRoles, DEFAULT_PANEL_NAME,
 };
 use log::warn;
 use serde::Deserialize;
 use serenity::model::id::{ChannelId, GuildId, RoleId};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::sync::{Arc, OnceLock, RwLock};
 
@@ -32,10 +34,34 @@ pub struct Config {
     pub application_token: String,
     pub pterodactyl_domain: String,
     pub pterodactyl_api_key: String,
+    /// Additional panels, besides the default one above
+    #[serde(default)]
+    pub pterodactyl_extra_panels: BTreeMap<String, PterodactylPanel>,
+    #[serde(default = "default_categories")]
+    pub pterodactyl_categories: BTreeMap<PterodactylServerCategory, PterodactylCategoryInfo>,
     pub pterodactyl_servers: Vec<PterodactylServer>,
+    #[serde(default)]
     pub pterodactyl_emails: PterodactylEmails,
+    #[serde(default)]
+    pub pterodactyl_tier_roles: PterodactylTierRoles,
     pub pterodactyl_perms: PterodactylAllPerms,
     pub pterodactyl_chat_bridges: Vec<PterodactylChatBridge>,
+    #[serde(default)]
+    pub pterodactyl_op_levels: BTreeMap<PterodactylServerCategory, u8>,
+    #[serde(default)]
+    pub role_whitelists: RoleWhitelists,
+    /// Permission levels for in-game `!` commands granted to the linked Minecraft accounts of
+    /// members with these roles
+    #[serde(default)]
+    pub game_command_roles: HashMap<RoleId, u8>,
+    #[serde(default)]
+    pub periodic_perms_sync: Option<PeriodicPermsSync>,
+    #[serde(default)]
+    pub resource_monitoring: ResourceMonitoring,
+    #[serde(default)]
+    pub lag_monitoring: LagMonitoring,
+    #[serde(default)]
+    pub console_rules: Vec<ConsoleRule>,
     pub special_channels: SpecialChannels,
     pub special_roles: SpecialRoles,
 }
@@ -44,11 +70,77 @@ impl Config {
     fn load() -> crate::Result<Config> {
         let file = File::open("config.json")?;
         let config: Config = serde_json::from_reader(file)?;
+        config.validate()?;
         config.lint();
         Ok(config)
     }
 
+    /// Rejects values the bot can't work with, unlike `lint` which only warns about likely mistakes.
+    fn validate(&self) -> crate::Result<()> {
+        check_duration(
+            "role_whitelists.grace_period_hours",
+            chrono::Duration::try_hours(self.role_whitelists.grace_period_hours),
+        )?;
+        let monitoring = &self.resource_monitoring;
+        check_duration(
+            "resource_monitoring.poll_interval_seconds",
+            i64::try_from(monitoring.poll_interval_seconds)
+                .ok()
+                // Gaps of three poll intervals break up the /stats chart
+                .and_then(|interval| interval.checked_mul(3))
+                .and_then(chrono::Duration::try_seconds),
+        )?;
+        check_duration(
+            "resource_monitoring.history_hours",
+            i64::try_from(monitoring.history_hours)
+                .ok()
+                .and_then(chrono::Duration::try_hours),
+        )?;
+        check_duration(
+            "resource_monitoring.alert_after_minutes",
+            i64::try_from(monitoring.alert_after_minutes)
+                .ok()
+                .and_then(chrono::Duration::try_minutes),
+        )?;
+        check_duration(
+            "lag_monitoring.window_minutes",
+            i64::try_from(self.lag_monitoring.window_minutes)
+                .ok()
+                .and_then(chrono::Duration::try_minutes),
+        )?;
+        Ok(())
+    }
+
     fn lint(&self) {
+        if self
+            .pterodactyl_extra_panels
+            .contains_key(DEFAULT_PANEL_NAME)
+        {
+            warn!(
+                "Extra panel named {} is shadowed by the default panel",
+                DEFAULT_PANEL_NAME
+            );
+        }
+        for server in &self.pterodactyl_servers {
+            if !self
+                .pterodactyl_panels()
+                .any(|(panel_name, _)| panel_name == server.panel)
+            {
+                warn!("Unknown panel {} of server {}", server.panel, server.name);
+            }
+            if !self.pterodactyl_categories.contains_key(&server.category) {
+                warn!(
+                    "Unknown category {:?} of server {}",
+                    server.category, server.name
+                );
+            }
+            if server.schematics_directory.is_some() && server.schematics_directory().is_none() {
+                warn!(
+                    "Schematics directory of server {} must be a relative path within the server",
+                    server.name
+                );
+            }
+        }
         let mut seen_bridge_servers = HashSet::new();
         let mut seen_bridge_channels = HashSet::new();
         for chat_bridge in &self.pterodactyl_chat_bridges {
@@ -60,6 +152,15 @@ impl Config {
                 {
                     warn!("Unknown server: {}", server_name);
                 }
+                if let Some(server) = self
+                    .pterodactyl_servers
+                    .iter()
+                    .find(|server| &server.name == server_name)
+                {
+                    if !self.category_info(server.category).bridgeable {
+                        warn!("Server {} is not of a bridgeable category", server_name);
+                    }
+                }
                 if !seen_bridge_servers.insert(server_name) {
                     warn!("Duplicate server: {}", server_name);
                 }
@@ -70,6 +171,83 @@ impl Config {
                 }
             }
         }
+        for (role, level) in &self.game_command_roles {
+            if *level > 4 {
+                warn!("Invalid game command level for role {}: {}", role, level);
+            }
+        }
+        for (category, level) in &self.pterodactyl_op_levels {
+            if !(1..=4).contains(level) {
+                warn!("Invalid op level for category {:?}: {}", category, level);
+            }
+        }
+        for tier_perms in [
+            &self.pterodactyl_perms.superadmin,
+            &self.pterodactyl_perms.admin,
+            &self.pterodactyl_perms.normal,
+        ] {
+            for server_name in tier_perms.server_overrides.keys() {
+                self.lint_server_name(server_name);
+            }
+        }
+        for user_perms in self.pterodactyl_perms.users.values().flatten() {
+            for server_name in &user_perms.servers {
+                self.lint_server_name(server_name);
+            }
+        }
+        let mut seen_rule_names = HashSet::new();
+        for rule in &self.console_rules {
+            if !seen_rule_names.insert(&rule.name) {
+                warn!("Duplicate console rule: {}", rule.name);
+            }
+            for server_name in &rule.servers {
+                self.lint_server_name(server_name);
+            }
+            for category in &rule.categories {
+                if !self.pterodactyl_categories.contains_key(category) {
+                    warn!(
+                        "Unknown category {:?} of console rule {}",
+                        category, rule.name
+                    );
+                }
+            }
+        }
+        for (role, categories) in &self.role_whitelists.roles {
+            for category in categories {
+                if !self.category_info(*category).whitelist_managed {
+                    warn!(
+                        "Role {} whitelists on unmanaged category {:?}",
+                        role, category
+                    );
+                }
+            }
+        }
+    }
+
+    fn lint_server_name(&self, server_name: &str) {
+        if !self
+            .pterodactyl_servers
+            .iter()
+            .any(|server| server.name == server_name)
+        {
+            warn!("Unknown server: {}", server_name);
+        }
+    }
+
+    pub fn pterodactyl_panels(&self) -> impl Iterator<Item = (&str, PterodactylPanel)> {
+        std::iter::once((
+            DEFAULT_PANEL_NAME,
+            PterodactylPanel {
+                domain: self.pterodactyl_domain.clone(),
+                api_key: self.pterodactyl_api_key.clone(),
+            },
+        ))
+        .chain(
+            self.pterodactyl_extra_panels
+                .iter()
+                .filter(|(name, _)| *name != DEFAULT_PANEL_NAME)
+                .map(|(name, panel)| (name.as_str(), panel.clone())),
+        )
     }
 
     pub fn pterodactyl_servers(
@@ -81,10 +259,33 @@ impl Config {
             .filter(move |server| filter.test(server.category))
     }
 
+    /// Config lint must use this rather than the methods on `PterodactylServerCategory`, which
+    /// read the global config.
+    pub fn category_info(&self, category: PterodactylServerCategory) -> PterodactylCategoryInfo {
+        self.pterodactyl_categories
+            .get(&category)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn default_op_level(&self, category: PterodactylServerCategory) -> u8 {
+        self.pterodactyl_op_levels
+            .get(&category)
+            .copied()
+            .unwrap_or(ops::DEFAULT_OP_LEVEL)
+    }
+
     pub fn chat_bridge_by_ptero_server_name(
         &self,
         server_name: &str,
     ) -> Option<&PterodactylChatBridge> {
+        let server = self
+            .pterodactyl_servers
+            .iter()
+            .find(|server| server.name == server_name)?;
+        if !self.category_info(server.category).bridgeable {
+            return None;
+        }
         self.pterodactyl_chat_bridges
             .iter()
             .find(|bridge| bridge.ptero_servers.iter().any(|name| name == server_name))
@@ -103,14 +304,143 @@ impl Config {
     }
 }
 
+/// Checks that a configured duration exists, isn't negative, and can be added to the current time.
+fn check_duration(name: &str, duration: Option<chrono::Duration>) -> crate::Result<()> {
+    match duration {
+        Some(duration)
+            if duration >= chrono::Duration::zero()
+                && chrono::Utc::now().checked_add_signed(duration).is_some() =>
+        {
+            Ok(())
+        }
+        _ => Err(crate::Error::Other(format!("{name} is out of range"))),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct SpecialChannels {
     pub applications: ChannelId,
     pub support: ChannelId,
+    #[serde(default)]
+    pub ban_log: Option<ChannelId>,
+    #[serde(default)]
+    pub staff: Option<ChannelId>,
+    #[serde(default)]
+    pub announcements: Option<ChannelId>,
 }
 
 #[derive(Deserialize)]
 pub struct SpecialRoles {
     pub panel_access: RoleId,
     pub channel_access: RoleId,
+    /// Pinged when players ask for staff from in game
+    #[serde(default)]
+    pub staff: Option<RoleId>,
+}
+
+impl SpecialRoles {
+    /// Whether a member with these roles counts as staff: they have panel access or the staff role.
+    pub fn is_staff(&self, roles: &[RoleId]) -> bool {
+        roles
+            .iter()
+            .any(|&role| role == self.panel_access || Some(role) == self.staff)
+    }
+}
+
+/// Discord roles which whitelist the linked Minecraft accounts of their members.
+#[derive(Default, Deserialize)]
+pub struct RoleWhitelists {
+    #[serde(default)]
+    pub roles: HashMap<RoleId, Vec<PterodactylServerCategory>>,
+    /// How long a player stays whitelisted after losing their role
+    #[serde(default)]
+    pub grace_period_hours: i64,
+}
+
+#[derive(Deserialize)]
+pub struct PeriodicPermsSync {
+    pub interval_minutes: u64,
+    /// Only alert staff about drifted perms instead of fixing them
+    #[serde(default)]
+    pub alert_only: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ResourceMonitoring {
+    #[serde(default = "default_resource_poll_interval_seconds")]
+    pub poll_interval_seconds: u64,
+    /// How much resource usage history to keep in memory
+    #[serde(default = "default_resource_history_hours")]
+    pub history_hours: u64,
+    /// Alert staff when usage stays above these percentages of a server's limits
+    #[serde(default)]
+    pub cpu_alert_percent: Option<f64>,
+    #[serde(default)]
+    pub memory_alert_percent: Option<f64>,
+    #[serde(default)]
+    pub disk_alert_percent: Option<f64>,
+    /// How long usage must stay above a threshold before staff are alerted
+    #[serde(default = "default_resource_alert_after_minutes")]
+    pub alert_after_minutes: u64,
+}
+
+impl Default for ResourceMonitoring {
+    fn default() -> Self {
+        ResourceMonitoring {
+            poll_interval_seconds: default_resource_poll_interval_seconds(),
+            history_hours: default_resource_history_hours(),
+            cpu_alert_percent: None,
+            memory_alert_percent: None,
+            disk_alert_percent: None,
+            alert_after_minutes: default_resource_alert_after_minutes(),
+        }
+    }
+}
+
+fn default_resource_poll_interval_seconds() -> u64 {
+    60
+}
+
+fn default_resource_history_hours() -> u64 {
+    48
+}
+
+fn default_resource_alert_after_minutes() -> u64 {
+    5
+}
+
+/// When to report lag warnings from server consoles to staff. A report is sent when either
+/// threshold is crossed within the window, or immediately on a watchdog warning.
+#[derive(Deserialize)]
+pub struct LagMonitoring {
+    #[serde(default = "default_lag_window_minutes")]
+    pub window_minutes: u64,
+    /// The number of lag warnings in the window
+    #[serde(default = "default_lag_events_threshold")]
+    pub events_threshold: usize,
+    /// The total time the server fell behind in the window
+    #[serde(default = "default_lag_ms_threshold")]
+    pub lag_ms_threshold: u64,
+}
+
+impl Default for LagMonitoring {
+    fn default() -> Self {
+        LagMonitoring {
+            window_minutes: default_lag_window_minutes(),
+            events_threshold: default_lag_events_threshold(),
+            lag_ms_threshold: default_lag_ms_threshold(),
+        }
+    }
+}
+
+fn default_lag_window_minutes() -> u64 {
+    10
+}
+
+fn default_lag_events_threshold() -> usize {
+    5
+}
+
+fn default_lag_ms_threshold() -> u64 {
+    10000
 }
@@ -0,0 +1,407 @@
+use crate::discord_bot::check_panel_access;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::{parse_duration, send_command_safe, PterodactylServerCategory};
+use crate::{config, ProtobotData};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use futures::future::join_all;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use serenity::builder::{
+    CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{
+    CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+};
+use serenity::model::id::UserId;
+use serenity::model::Colour;
+use std::time::Duration;
+
+/// How often to check for scheduled announcements which are due
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+pub(super) fn create_command() -> CreateCommand {
+    CreateCommand::new("announce")
+        .description("Announce something on Discord and the Minecraft servers")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "send",
+                "Sends or schedules an announcement",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "message", "The announcement")
+                    .required(true),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "title",
+                "Shown as a title on screen in game",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "categories",
+                "Comma separated server categories to announce on (default all)",
+            ))
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "at",
+                "When to send it, either in some time (2h30m) or at a UTC time (2024-05-01 18:00)",
+            )),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::SubCommand,
+            "scheduled",
+            "Lists scheduled announcements",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "cancel",
+                "Cancels a scheduled announcement",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::Integer, "id", "The announcement ID")
+                    .required(true),
+            ),
+        )
+}
+
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    if !check_panel_access(ctx, command).await? {
+        return Ok(());
+    }
+    let [CommandDataOption {
+        name,
+        value: CommandDataOptionValue::SubCommand(options),
+        ..
+    }] = &command.data.options[..]
+    else {
+        return Ok(());
+    };
+    let option = |name: &str| {
+        options
+            .iter()
+            .find(|option| option.name == name)
+            .map(|option| &option.value)
+    };
+
+    let message = match &name[..] {
+        "send" => {
+            let categories = option("categories").and_then(CommandDataOptionValue::as_str);
+            let at = option("at").and_then(CommandDataOptionValue::as_str);
+            let (categories, send_at) =
+                match (parse_categories(categories), at.map(parse_send_time)) {
+                    (Err(message), _) => return respond(ctx, command, message, true).await,
+                    (_, Some(None)) => {
+                        return respond(
+                            ctx,
+                            command,
+                            format!("Invalid time {}", at.unwrap_or_default()),
+                            true,
+                        )
+                        .await
+                    }
+                    (Ok(categories), send_at) => (categories, send_at.flatten()),
+                };
+            let mut announcement = ScheduledAnnouncement {
+                id: 0,
+                title: option("title")
+                    .and_then(CommandDataOptionValue::as_str)
+                    .map(str::to_owned),
+                message: option("message")
+                    .and_then(CommandDataOptionValue::as_str)
+                    .unwrap_or_default()
+                    .to_owned(),
+                categories,
+                send_at: 0,
+                author: command.user.id,
+            };
+            match send_at {
+                Some(send_at) => {
+                    announcement.send_at = send_at.timestamp();
+                    schedule(announcement).await
+                }
+                None => {
+                    command
+                        .create_response(
+                            &ctx.http,
+                            CreateInteractionResponse::Defer(
+                                CreateInteractionResponseMessage::new(),
+                            ),
+                        )
+                        .await?;
+                    let message = send_announcement(data, &announcement).await;
+                    command
+                        .edit_response(&ctx.http, EditInteractionResponse::new().content(message))
+                        .await?;
+                    return Ok(());
+                }
+            }
+        }
+        "scheduled" => list_scheduled().await,
+        "cancel" => {
+            let id = option("id")
+                .and_then(CommandDataOptionValue::as_i64)
+                .unwrap_or_default();
+            cancel(id).await
+        }
+        _ => return Ok(()),
+    };
+    respond(ctx, command, message, false).await
+}
+
+async fn respond(
+    ctx: &Context,
+    command: &CommandInteraction,
+    message: impl Into<String>,
+    ephemeral: bool,
+) -> crate::Result<()> {
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(message)
+                    .ephemeral(ephemeral),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn schedule(mut announcement: ScheduledAnnouncement) -> String {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    announcement.id = storage
+        .scheduled_announcements
+        .iter()
+        .map(|announcement| announcement.id + 1)
+        .max()
+        .unwrap_or(1);
+    let message = format!(
+        "Scheduled announcement #{} for <t:{}:F>",
+        announcement.id, announcement.send_at
+    );
+    storage.scheduled_announcements.push(announcement);
+    storage.save().await;
+    message
+}
+
+async fn list_scheduled() -> String {
+    let storage = GuildStorage::get(config::get().guild_id).await;
+    if storage.scheduled_announcements.is_empty() {
+        return "There are no scheduled announcements".to_owned();
+    }
+    let mut announcements: Vec<_> = storage.scheduled_announcements.iter().collect();
+    announcements.sort_by_key(|announcement| announcement.send_at);
+    let mut message = "**Scheduled announcements**".to_owned();
+    for announcement in announcements {
+        message += &format!(
+            "\n#{} <t:{}:R> by <@{}> to {}: {}",
+            announcement.id,
+            announcement.send_at,
+            announcement.author,
+            announcement.category_names(),
+            announcement.title.as_ref().unwrap_or(&announcement.message)
+        );
+    }
+    message
+}
+
+async fn cancel(id: i64) -> String {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let count = storage.scheduled_announcements.len();
+    storage
+        .scheduled_announcements
+        .retain(|announcement| i64::from(announcement.id) != id);
+    if storage.scheduled_announcements.len() == count {
+        storage.discard();
+        return format!("There is no scheduled announcement #{id}");
+    }
+    storage.save().await;
+    format!("Cancelled announcement #{id}")
+}
+
+/// Sends scheduled announcements once they are due.
+pub(crate) async fn run_scheduler(data: ProtobotData) {
+    loop {
+        tokio::select! {
+            _ = crate::wait_shutdown() => break,
+            _ = tokio::time::sleep(SCHEDULE_CHECK_INTERVAL) => {}
+        }
+        let now = Utc::now().timestamp();
+        // Due announcements are removed before sending, so that they are never sent twice
+        let due = {
+            let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+            let (due, pending) = storage
+                .scheduled_announcements
+                .drain(..)
+                .partition::<Vec<_>, _>(|announcement| announcement.send_at <= now);
+            storage.scheduled_announcements = pending;
+            if due.is_empty() {
+                storage.discard();
+            } else {
+                storage.save().await;
+            }
+            due
+        };
+        for announcement in due {
+            let result = send_announcement(&data, &announcement).await;
+            info!(
+                "Sent scheduled announcement #{}: {}",
+                announcement.id, result
+            );
+        }
+    }
+}
+
+/// Posts an announcement to the announcements channel and every Minecraft server of its
+/// categories, returning a summary of where it was sent.
+async fn send_announcement(data: &ProtobotData, announcement: &ScheduledAnnouncement) -> String {
+    let config = config::get();
+    let mut summary = Vec::new();
+
+    match config.special_channels.announcements {
+        Some(channel) => {
+            let mut embed = CreateEmbed::new()
+                .description(&announcement.message)
+                .colour(Colour::GOLD)
+                .footer(CreateEmbedFooter::new(format!(
+                    "Announced on {}",
+                    announcement.category_names()
+                )));
+            if let Some(title) = &announcement.title {
+                embed = embed.title(title);
+            }
+            match channel
+                .send_message(&data.discord_handle, CreateMessage::new().embed(embed))
+                .await
+            {
+                Ok(_) => summary.push("Posted on Discord".to_owned()),
+                Err(err) => {
+                    error!("Failed to post announcement: {}", err);
+                    summary.push(format!("Failed to post on Discord: {err}"));
+                }
+            }
+        }
+        None => summary.push("No announcements channel is configured".to_owned()),
+    }
+
+    let servers: Vec<_> = config
+        .pterodactyl_servers(|category: PterodactylServerCategory| {
+            config.category_info(category).minecraft && announcement.categories.contains(&category)
+        })
+        .collect();
+    let results = join_all(servers.iter().map(|server| async move {
+        let ptero_server = data.pterodactyl.get_server(server)?;
+        if let Some(title) = &announcement.title {
+            send_command_safe(
+                &ptero_server,
+                format!("title @a title {}", json!({"text": title, "color": "gold"})),
+            )
+            .await?;
+        }
+        let prefix = match &announcement.title {
+            Some(title) => format!("[{title}] "),
+            None => "[Announcement] ".to_owned(),
+        };
+        send_command_safe(
+            &ptero_server,
+            format!(
+                "tellraw @a {}",
+                json!([
+                    {"text": prefix, "color": "gold", "bold": true},
+                    {"text": announcement.message, "color": "white", "bold": false},
+                ])
+            ),
+        )
+        .await
+    }))
+    .await;
+
+    let failed: Vec<_> = servers
+        .iter()
+        .zip(results)
+        .filter_map(|(server, result)| {
+            let err = result.err()?;
+            error!("Failed to announce on {}: {}", server.name, err);
+            Some(server.display_name.as_str())
+        })
+        .collect();
+    summary.push(format!(
+        "Announced on {} of {} servers",
+        servers.len() - failed.len(),
+        servers.len()
+    ));
+    if !failed.is_empty() {
+        summary.push(format!("Failed on {}", failed.join(", ")));
+    }
+    summary.join("\n")
+}
+
+/// Parses comma separated category names, defaulting to every Minecraft category.
+fn parse_categories(categories: Option<&str>) -> Result<Vec<PterodactylServerCategory>, String> {
+    let config = config::get();
+    let minecraft_categories = config
+        .pterodactyl_categories
+        .iter()
+        .filter(|(_, info)| info.minecraft)
+        .map(|(&category, _)| category);
+    let Some(categories) = categories.filter(|categories| categories.trim() != "all") else {
+        return Ok(minecraft_categories.collect());
+    };
+    let categories = categories
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            minecraft_categories
+                .clone()
+                .find(|category| category.name() == name)
+                .ok_or_else(|| format!("Unknown Minecraft server category {name}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if categories.is_empty() {
+        return Err("No categories given".to_owned());
+    }
+    Ok(categories)
+}
+
+/// Parses either a delay such as `2h30m` or a UTC time such as `2024-05-01 18:00`, which must be in
+/// the future.
+fn parse_send_time(time: &str) -> Option<DateTime<Utc>> {
+    let time = time.trim();
+    let send_at = match parse_duration(time) {
+        Some(delay) => Utc::now().checked_add_signed(delay)?,
+        None => NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+            .ok()?
+            .and_utc(),
+    };
+    (send_at > Utc::now()).then_some(send_at)
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScheduledAnnouncement {
+    id: u32,
+    title: Option<String>,
+    message: String,
+    categories: Vec<PterodactylServerCategory>,
+    send_at: i64,
+    author: UserId,
+}
+
+impl ScheduledAnnouncement {
+    fn category_names(&self) -> String {
+        self.categories
+            .iter()
+            .map(PterodactylServerCategory::name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
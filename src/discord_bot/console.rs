@@ -0,0 +1,441 @@
+use crate::command_sender::CommandSender;
+use crate::discord_bot::check_panel_access;
+use crate::{stdin, ProtobotData};
+use log::info;
+use serenity::builder::{
+    CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{
+    CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+};
+
+/// A slash command which runs a console command. Each subcommand maps to the console subcommand
+/// of the same name, and its options are passed as positional arguments in declaration order.
+struct ConsoleCommand {
+    name: &'static str,
+    description: &'static str,
+    subcommands: &'static [ConsoleSubcommand],
+}
+
+struct ConsoleSubcommand {
+    name: &'static str,
+    description: &'static str,
+    options: &'static [ConsoleOption],
+}
+
+struct ConsoleOption {
+    name: &'static str,
+    description: &'static str,
+    kind: CommandOptionType,
+    required: bool,
+    /// The argument passed to the console command if this option is omitted but a later one isn't
+    placeholder: Option<&'static str>,
+}
+
+const fn required(name: &'static str, description: &'static str) -> ConsoleOption {
+    ConsoleOption {
+        name,
+        description,
+        kind: CommandOptionType::String,
+        required: true,
+        placeholder: None,
+    }
+}
+
+const fn optional(
+    name: &'static str,
+    description: &'static str,
+    kind: CommandOptionType,
+    placeholder: Option<&'static str>,
+) -> ConsoleOption {
+    ConsoleOption {
+        name,
+        description,
+        kind,
+        required: false,
+        placeholder,
+    }
+}
+
+const CONSOLE_COMMANDS: &[ConsoleCommand] = &[
+    ConsoleCommand {
+        name: "ban",
+        description: "Manage server bans",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "add",
+                description: "Bans a player on every server in a category",
+                options: &[
+                    required("player", "The player to ban"),
+                    required("category", "The server category, or all"),
+                    optional("reason", "The ban reason", CommandOptionType::String, None),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "remove",
+                description: "Unbans a player on every server in a category",
+                options: &[
+                    required("player", "The player to unban"),
+                    required("category", "The server category, or all"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "add_ip",
+                description: "Bans an IP address on every server in a category",
+                options: &[
+                    required("ip", "The IP address to ban"),
+                    required("category", "The server category, or all"),
+                    optional("reason", "The ban reason", CommandOptionType::String, None),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "remove_ip",
+                description: "Unbans an IP address on every server in a category",
+                options: &[
+                    required("ip", "The IP address to unban"),
+                    required("category", "The server category, or all"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "list",
+                description: "Lists the bans of a category",
+                options: &[required("category", "The server category")],
+            },
+        ],
+    },
+    ConsoleCommand {
+        name: "links",
+        description: "Manage linked Minecraft accounts",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "list",
+                description:
+                    "Lists linked accounts and the categories their roles whitelist them on",
+                options: &[],
+            },
+            ConsoleSubcommand {
+                name: "sync",
+                description: "Re-syncs the role whitelists of every linked account",
+                options: &[],
+            },
+        ],
+    },
+    ConsoleCommand {
+        name: "maintenance",
+        description: "Lock servers down to staff for maintenance",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "on",
+                description: "Whitelists only staff and kicks everyone else",
+                options: &[
+                    required("target", "The server or server category"),
+                    optional(
+                        "message",
+                        "The message players are kicked with",
+                        CommandOptionType::String,
+                        None,
+                    ),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "off",
+                description: "Restores the whitelist from before maintenance",
+                options: &[required("target", "The server or server category")],
+            },
+            ConsoleSubcommand {
+                name: "list",
+                description: "Lists the servers in maintenance",
+                options: &[],
+            },
+        ],
+    },
+    ConsoleCommand {
+        name: "ops",
+        description: "Manage server ops",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "add",
+                description: "Ops a player on every server in a category",
+                options: &[
+                    required("player", "The player to op"),
+                    required("category", "The server category, or all"),
+                    optional(
+                        "level",
+                        "The op level, defaults to the category's default",
+                        CommandOptionType::Integer,
+                        Some("default"),
+                    ),
+                    optional(
+                        "bypasses_player_limit",
+                        "Whether the player can join when the server is full",
+                        CommandOptionType::Boolean,
+                        None,
+                    ),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "remove",
+                description: "De-ops a player on every server in a category",
+                options: &[
+                    required("player", "The player to de-op"),
+                    required("category", "The server category, or all"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "list",
+                description: "Lists the ops of a category",
+                options: &[required("category", "The server category")],
+            },
+            ConsoleSubcommand {
+                name: "sync",
+                description: "Makes the ops of every server in a category match the first server",
+                options: &[required("category", "The server category, or all")],
+            },
+        ],
+    },
+    ConsoleCommand {
+        name: "playtime",
+        description: "Player activity reports",
+        subcommands: &[ConsoleSubcommand {
+            name: "inactive",
+            description: "Lists whitelisted players who haven't joined for a number of days",
+            options: &[
+                required("category", "The server category"),
+                required("days", "How many days without joining"),
+            ],
+        }],
+    },
+    ConsoleCommand {
+        name: "rules",
+        description: "Console trigger rules",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "list",
+                description: "Lists the console trigger rules",
+                options: &[],
+            },
+            ConsoleSubcommand {
+                name: "test",
+                description: "Shows which rules a console line would trigger, without running them",
+                options: &[
+                    required("server", "The server the line is from"),
+                    required("line", "The console line"),
+                ],
+            },
+        ],
+    },
+    ConsoleCommand {
+        name: "servers",
+        description: "Discover servers on the panel",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "list",
+                description: "Lists panel servers and whether they are in the config",
+                options: &[],
+            },
+            ConsoleSubcommand {
+                name: "generate",
+                description: "Generates config entries for panel servers",
+                options: &[
+                    required("category", "The category of the servers"),
+                    required("ids", "Space separated panel ids of the servers"),
+                ],
+            },
+        ],
+    },
+    ConsoleCommand {
+        name: "whitelist",
+        description: "Manage server whitelists",
+        subcommands: &[
+            ConsoleSubcommand {
+                name: "add",
+                description: "Whitelists a player on every server in a category",
+                options: &[
+                    required("player", "The player to whitelist"),
+                    required("category", "The server category, or all"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "temp",
+                description: "Temporarily whitelists a player on every server in a category",
+                options: &[
+                    required("player", "The player to whitelist"),
+                    required("category", "The server category, or all"),
+                    required(
+                        "duration",
+                        "How long until the player is removed, e.g. 2w or 1d12h",
+                    ),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "extend",
+                description: "Extends a temporary whitelist entry",
+                options: &[
+                    required("player", "The temporarily whitelisted player"),
+                    required("category", "The server category, or all"),
+                    required("duration", "How long to extend the entry by, e.g. 1w"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "permanent",
+                description: "Makes a temporary whitelist entry permanent",
+                options: &[
+                    required("player", "The temporarily whitelisted player"),
+                    required("category", "The server category, or all"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "remove",
+                description: "Unwhitelists a player on every server in a category",
+                options: &[
+                    required("player", "The player to unwhitelist"),
+                    required("category", "The server category, or all"),
+                ],
+            },
+            ConsoleSubcommand {
+                name: "list",
+                description: "Lists the whitelisted players of a category",
+                options: &[required("category", "The server category")],
+            },
+            ConsoleSubcommand {
+                name: "expiries",
+                description: "Lists the temporary whitelist entries",
+                options: &[],
+            },
+        ],
+    },
+];
+
+pub(super) fn create_commands() -> impl Iterator<Item = CreateCommand> {
+    CONSOLE_COMMANDS.iter().map(|command| {
+        command.subcommands.iter().fold(
+            CreateCommand::new(command.name).description(command.description),
+            |builder, subcommand| {
+                builder.add_option(subcommand.options.iter().fold(
+                    CreateCommandOption::new(
+                        CommandOptionType::SubCommand,
+                        subcommand.name,
+                        subcommand.description,
+                    ),
+                    |builder, option| {
+                        builder.add_sub_option(
+                            CreateCommandOption::new(option.kind, option.name, option.description)
+                                .required(option.required),
+                        )
+                    },
+                ))
+            },
+        )
+    })
+}
+
+pub(super) fn is_console_command(name: &str) -> bool {
+    CONSOLE_COMMANDS.iter().any(|command| command.name == name)
+}
+
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    if !check_panel_access(ctx, command).await? {
+        return Ok(());
+    }
+
+    let Some(args) = to_console_args(command) else {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new().content("Invalid command"),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+        )
+        .await?;
+
+    let sender = CommandSender::capturing(command.user.name.clone());
+    info!(
+        "Discord user \"{}\" (ID {}) ran console command \"{}\"",
+        sender.name(),
+        command.user.id,
+        args.join(" ")
+    );
+    let result = stdin::handle_command(data, &sender, args.iter().map(String::as_str)).await;
+    let mut output = sender.take_output();
+    if let Err(err) = result {
+        if !output.is_empty() {
+            output.push('\n');
+        }
+        output += &format!("Error: {err}");
+    }
+    if output.is_empty() {
+        output = "Done".to_owned();
+    }
+
+    let response = if output.len() > 1990 {
+        EditInteractionResponse::new()
+            .content("Output attached")
+            .new_attachment(CreateAttachment::bytes(output, "output.txt"))
+    } else {
+        EditInteractionResponse::new().content(format!("```\n{output}\n```"))
+    };
+    command.edit_response(&ctx.http, response).await?;
+
+    Ok(())
+}
+
+fn to_console_args(command: &CommandInteraction) -> Option<Vec<String>> {
+    let console_command = CONSOLE_COMMANDS
+        .iter()
+        .find(|console_command| console_command.name == command.data.name)?;
+    let [CommandDataOption {
+        name: subcommand_name,
+        value: CommandDataOptionValue::SubCommand(options),
+        ..
+    }] = &command.data.options[..]
+    else {
+        return None;
+    };
+    let subcommand = console_command
+        .subcommands
+        .iter()
+        .find(|subcommand| subcommand.name == subcommand_name)?;
+
+    let mut args = vec![console_command.name.to_owned(), subcommand.name.to_owned()];
+    let mut omitted_placeholders: Vec<Option<&str>> = Vec::new();
+    for option in subcommand.options {
+        let value = options
+            .iter()
+            .find(|value| value.name == option.name)
+            .and_then(|value| match &value.value {
+                CommandDataOptionValue::String(value) => Some(value.clone()),
+                CommandDataOptionValue::Integer(value) => Some(value.to_string()),
+                CommandDataOptionValue::Number(value) => Some(value.to_string()),
+                CommandDataOptionValue::Boolean(value) => Some(value.to_string()),
+                CommandDataOptionValue::User(value) => Some(value.to_string()),
+                CommandDataOptionValue::Channel(value) => Some(value.to_string()),
+                CommandDataOptionValue::Role(value) => Some(value.to_string()),
+                _ => None,
+            });
+        match value {
+            Some(value) => {
+                for placeholder in omitted_placeholders.drain(..) {
+                    args.push(placeholder?.to_owned());
+                }
+                args.extend(value.split_whitespace().map(str::to_owned));
+            }
+            None => omitted_placeholders.push(option.placeholder),
+        }
+    }
+    Some(args)
+}
@@ -1,9 +1,14 @@
+use crate::discord_bot::announce::ScheduledAnnouncement;
 use crate::discord_bot::april_fools_channel::AprilFoolsChannels;
 use crate::discord_bot::chess::ChessState;
 use crate::discord_bot::permanent_latest::PermanentLatestInfo;
 use crate::discord_bot::role::RoleData;
 use crate::discord_bot::roletoggle::RoleToggleInfo;
 use crate::discord_bot::welcome_message::WelcomeMessageData;
+use crate::pterodactyl::maintenance::MaintenanceState;
+use crate::pterodactyl::playtime::PlayerActivity;
+use crate::pterodactyl::role_whitelist::{LinkedAccount, RoleWhitelistGrant};
+use crate::pterodactyl::whitelist::WhitelistExpiry;
 use dashmap::mapref::entry::Entry;
 use dashmap::mapref::one::{Ref, RefMut};
 use dashmap::DashMap;
@@ -53,6 +58,22 @@ pub struct GuildStorage {
     pub social_credit: HashMap<UserId, i32>,
     #[serde(default)]
     pub april_fools_channels: AprilFoolsChannels,
+    #[serde(default)]
+    pub whitelist_expiries: Vec<WhitelistExpiry>,
+    #[serde(default)]
+    pub linked_accounts: HashMap<UserId, LinkedAccount>,
+    #[serde(default)]
+    pub role_whitelist_grants: Vec<RoleWhitelistGrant>,
+    #[serde(default)]
+    pub panel_emails: HashMap<UserId, String>,
+    /// Playtime by server name, then by lowercase player name
+    #[serde(default)]
+    pub player_activity: HashMap<String, HashMap<String, PlayerActivity>>,
+    #[serde(default)]
+    pub scheduled_announcements: Vec<ScheduledAnnouncement>,
+    /// The servers in maintenance by server name
+    #[serde(default)]
+    pub maintenance: HashMap<String, MaintenanceState>,
 }
 
 impl Default for GuildStorage {
@@ -73,6 +94,13 @@ impl Default for GuildStorage {
             counters: HashMap::new(),
             social_credit: HashMap::new(),
             april_fools_channels: AprilFoolsChannels::default(),
+            whitelist_expiries: Vec::new(),
+            linked_accounts: HashMap::new(),
+            role_whitelist_grants: Vec::new(),
+            panel_emails: HashMap::new(),
+            player_activity: HashMap::new(),
+            scheduled_announcements: Vec::new(),
+            maintenance: HashMap::new(),
         }
     }
 }
@@ -0,0 +1,81 @@
+use crate::pterodactyl::role_whitelist;
+use crate::ProtobotData;
+use serenity::builder::{
+    CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+
+pub(super) fn create_commands() -> [CreateCommand; 2] {
+    [
+        CreateCommand::new("link")
+            .description("Links your Minecraft account, for servers you have access to via roles")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "player",
+                    "Your Minecraft username",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("unlink").description("Unlinks your Minecraft account"),
+    ]
+}
+
+pub(super) async fn link(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    let Some(player) = command
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == "player")
+        .and_then(|option| option.value.as_str())
+    else {
+        return Ok(());
+    };
+    defer_ephemeral(ctx, command).await?;
+    let message = match role_whitelist::link_account(data, command.user.id, player).await {
+        Ok(Ok((name, verification_code))) => format!(
+            "Linked your account to {name}. To prove it's yours, type `!link {verification_code}` in chat on one of our servers you can already join. Servers you have access to via roles whitelist it once it's verified"
+        ),
+        Ok(Err(message)) => message,
+        Err(err) => format!("Failed to link your account: {err}"),
+    };
+    command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(message))
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn unlink(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    defer_ephemeral(ctx, command).await?;
+    let message = match role_whitelist::unlink_account(data, command.user.id).await {
+        Ok(Some(name)) => format!("Unlinked your account from {name}"),
+        Ok(None) => "Your account is not linked".to_owned(),
+        Err(err) => format!("Failed to unlink your account: {err}"),
+    };
+    command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(message))
+        .await?;
+    Ok(())
+}
+
+async fn defer_ephemeral(ctx: &Context, command: &CommandInteraction) -> crate::Result<()> {
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(
+                CreateInteractionResponseMessage::new().ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,348 @@
+use crate::config;
+use crate::discord_bot::check_panel_access;
+use crate::pterodactyl::PterodactylServer;
+use crate::ProtobotData;
+use chrono::{NaiveDate, Utc};
+use flate2::read::GzDecoder;
+use regex::{Regex, RegexBuilder};
+use serenity::builder::{
+    CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{
+    CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+};
+use std::collections::VecDeque;
+use std::io::Read;
+
+const LOGS_DIRECTORY: &str = "logs";
+/// The maximum amount of decompressed log data scanned by one search
+const MAX_SCANNED_BYTES: u64 = 256 * 1024 * 1024;
+const MAX_MATCHES: usize = 1000;
+const DEFAULT_CONTEXT_LINES: usize = 2;
+const MAX_CONTEXT_LINES: usize = 10;
+
+pub(super) fn create_command() -> CreateCommand {
+    CreateCommand::new("logs")
+        .description("Search server logs")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "search",
+                "Searches the current and archived logs of a server",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "server", "The server name")
+                    .required(true),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "pattern",
+                    "A case insensitive regex to search for",
+                )
+                .required(true),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "dates",
+                "A date (2024-05-01) or range of dates (2024-05-01..2024-05-07) to search",
+            ))
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "context",
+                    "How many lines to show around each match (default 2)",
+                )
+                .min_int_value(0)
+                .max_int_value(MAX_CONTEXT_LINES as u64),
+            ),
+        )
+}
+
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    if !check_panel_access(ctx, command).await? {
+        return Ok(());
+    }
+    let [CommandDataOption {
+        value: CommandDataOptionValue::SubCommand(options),
+        ..
+    }] = &command.data.options[..]
+    else {
+        return Ok(());
+    };
+    let option = |name: &str| {
+        options
+            .iter()
+            .find(|option| option.name == name)
+            .map(|option| &option.value)
+    };
+    let server_name = option("server")
+        .and_then(CommandDataOptionValue::as_str)
+        .unwrap_or_default();
+    let pattern = option("pattern")
+        .and_then(CommandDataOptionValue::as_str)
+        .unwrap_or_default();
+    let dates = option("dates").and_then(CommandDataOptionValue::as_str);
+    let context_lines = option("context")
+        .and_then(CommandDataOptionValue::as_i64)
+        .map_or(DEFAULT_CONTEXT_LINES, |lines| {
+            (lines.max(0) as usize).min(MAX_CONTEXT_LINES)
+        });
+
+    let config = config::get();
+    let error = match (
+        config
+            .pterodactyl_servers
+            .iter()
+            .find(|server| server.name == server_name),
+        RegexBuilder::new(pattern).case_insensitive(true).build(),
+        dates.map_or(Some(DateRange::ALL), DateRange::parse),
+    ) {
+        (None, _, _) => format!("Unknown server {server_name}"),
+        (_, Err(err), _) => format!("Invalid pattern: {err}"),
+        (_, _, None) => format!("Invalid dates {}", dates.unwrap_or_default()),
+        (Some(server), Ok(regex), Some(date_range)) => {
+            command
+                .create_response(
+                    &ctx.http,
+                    CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+                )
+                .await?;
+            let response = match search_logs(data, server, regex, date_range, context_lines).await {
+                Ok(result) => result.into_response(server, pattern),
+                Err(err) => {
+                    EditInteractionResponse::new().content(format!("Failed to search logs: {err}"))
+                }
+            };
+            command.edit_response(&ctx.http, response).await?;
+            return Ok(());
+        }
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(error)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn search_logs(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    regex: Regex,
+    date_range: DateRange,
+    context_lines: usize,
+) -> crate::Result<SearchResult> {
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let today = Utc::now().date_naive();
+
+    // Archived logs are named like 2024-05-01-1.log.gz, and the newest are searched first
+    let mut log_files: Vec<_> = ptero_server
+        .list_files(LOGS_DIRECTORY)
+        .await?
+        .into_iter()
+        .filter(|file| file.is_file)
+        .filter_map(|file| {
+            let date = if file.name == "latest.log" {
+                today
+            } else if file.name.ends_with(".log.gz") {
+                NaiveDate::parse_from_str(file.name.get(..10)?, "%Y-%m-%d").ok()?
+            } else {
+                return None;
+            };
+            date_range.contains(date).then_some((date, file.name))
+        })
+        .collect();
+    log_files.sort_by(|a, b| b.cmp(a));
+
+    let mut result = SearchResult::default();
+    for (_, file_name) in log_files {
+        if result.scanned_bytes >= MAX_SCANNED_BYTES || result.matches >= MAX_MATCHES {
+            result.truncated = true;
+            break;
+        }
+        let download_url = ptero_server
+            .get_file_download_url(format!("{LOGS_DIRECTORY}/{file_name}"))
+            .await?;
+        let contents = reqwest::get(download_url)
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+
+        let max_bytes = MAX_SCANNED_BYTES - result.scanned_bytes;
+        let max_matches = MAX_MATCHES - result.matches;
+        let regex = regex.clone();
+        let file_result = tokio::task::spawn_blocking(move || {
+            search_file(
+                &file_name,
+                &contents,
+                &regex,
+                context_lines,
+                max_bytes,
+                max_matches,
+            )
+        })
+        .await??;
+        result.scanned_bytes += file_result.scanned_bytes;
+        result.matches += file_result.matches;
+        result.files_searched += 1;
+        result.truncated |= file_result.truncated;
+        result.output += &file_result.output;
+    }
+    Ok(result)
+}
+
+/// Searches one log file, decompressing it if needed, and formats matches like grep would.
+fn search_file(
+    file_name: &str,
+    contents: &[u8],
+    regex: &Regex,
+    context_lines: usize,
+    max_bytes: u64,
+    max_matches: usize,
+) -> crate::Result<SearchResult> {
+    let mut decompressed = Vec::new();
+    let reader: Box<dyn Read> = if file_name.ends_with(".gz") {
+        Box::new(GzDecoder::new(contents))
+    } else {
+        Box::new(contents)
+    };
+    // Read one more byte than allowed, to tell whether the file was cut off
+    reader.take(max_bytes + 1).read_to_end(&mut decompressed)?;
+    let mut truncated = decompressed.len() as u64 > max_bytes;
+    decompressed.truncate(max_bytes as usize);
+    let text = String::from_utf8_lossy(&decompressed);
+
+    let mut output = String::new();
+    let mut matches = 0;
+    let mut before = VecDeque::with_capacity(context_lines);
+    let mut after_remaining = 0;
+    let mut last_printed_line = None;
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        if regex.is_match(line) {
+            if matches >= max_matches {
+                truncated = true;
+                break;
+            }
+            matches += 1;
+            let first_line = line_number - before.len();
+            if last_printed_line.is_some_and(|last_printed_line| last_printed_line + 1 < first_line)
+            {
+                output += "--\n";
+            }
+            for (offset, before_line) in before.drain(..).enumerate() {
+                output += &format!("{}:{}- {}\n", file_name, first_line + offset, before_line);
+            }
+            output += &format!("{file_name}:{line_number}: {line}\n");
+            last_printed_line = Some(line_number);
+            after_remaining = context_lines;
+        } else if after_remaining > 0 {
+            output += &format!("{file_name}:{line_number}- {line}\n");
+            last_printed_line = Some(line_number);
+            after_remaining -= 1;
+        } else if context_lines > 0 {
+            if before.len() == context_lines {
+                before.pop_front();
+            }
+            before.push_back(line);
+        }
+    }
+    if matches > 0 {
+        output += "\n";
+    }
+
+    Ok(SearchResult {
+        output,
+        matches,
+        scanned_bytes: decompressed.len() as u64,
+        files_searched: 1,
+        truncated,
+    })
+}
+
+#[derive(Default)]
+struct SearchResult {
+    output: String,
+    matches: usize,
+    scanned_bytes: u64,
+    files_searched: usize,
+    /// Whether the search stopped early because it reached a limit
+    truncated: bool,
+}
+
+impl SearchResult {
+    fn into_response(self, server: &PterodactylServer, pattern: &str) -> EditInteractionResponse {
+        let mut summary = format!(
+            "Found {} matches for `{}` in {} log files of {}",
+            self.matches, pattern, self.files_searched, server.display_name
+        );
+        if self.truncated {
+            summary += &format!(
+                " (stopped early after {} matches or {} MiB of logs)",
+                MAX_MATCHES,
+                MAX_SCANNED_BYTES / 1024 / 1024
+            );
+        }
+        let response = EditInteractionResponse::new().content(summary);
+        if self.matches == 0 {
+            response
+        } else {
+            response.new_attachment(CreateAttachment::bytes(
+                self.output,
+                format!("{}_logs.txt", server.name),
+            ))
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DateRange {
+    from: NaiveDate,
+    to: NaiveDate,
+}
+
+impl DateRange {
+    const ALL: DateRange = DateRange {
+        from: NaiveDate::MIN,
+        to: NaiveDate::MAX,
+    };
+
+    /// Parses either a single date or an inclusive range of dates separated by `..`.
+    fn parse(dates: &str) -> Option<DateRange> {
+        let parse_date = |date: &str| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d").ok();
+        match dates.split_once("..") {
+            Some((from, to)) => {
+                let range = DateRange {
+                    from: parse_date(from)?,
+                    to: parse_date(to)?,
+                };
+                (range.from <= range.to).then_some(range)
+            }
+            None => {
+                let date = parse_date(dates)?;
+                Some(DateRange {
+                    from: date,
+                    to: date,
+                })
+            }
+        }
+    }
+
+    fn contains(self, date: NaiveDate) -> bool {
+        self.from <= date && date <= self.to
+    }
+}
@@ -0,0 +1,304 @@
+use crate::config;
+use crate::discord_bot::server_or_default_smp;
+use crate::pterodactyl::whitelist::Player;
+use crate::pterodactyl::{maintenance, PterodactylServer};
+use crate::ProtobotData;
+use chrono::{DateTime, Utc};
+use futures::future;
+use futures::stream::{self, StreamExt};
+use log::warn;
+use serde::Deserialize;
+use serenity::builder::{
+    CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use tokio::sync::Mutex;
+
+const STATS_DIRECTORY: &str = "world/stats";
+/// How long fetched stats are reused. Stats are only fetched again once someone asks for them after
+/// that, so leaderboards can be this much older than the time it takes to fetch them.
+const STATS_CACHE_TTL: chrono::Duration = chrono::Duration::minutes(15);
+/// How many stats files are downloaded at once
+const CONCURRENT_DOWNLOADS: usize = 4;
+const LEADERBOARD_SIZE: usize = 10;
+
+/// Friendly names for commonly requested stats
+const STAT_ALIASES: &[(&str, &str)] = &[
+    ("blocks_mined", "mined"),
+    ("items_crafted", "crafted"),
+    ("items_used", "used"),
+    ("mobs_killed", "killed"),
+    ("deaths", "custom:deaths"),
+    ("distance_flown", "custom:aviate_one_cm"),
+    ("distance_walked", "custom:walk_one_cm"),
+    ("playtime", "custom:play_time"),
+];
+
+/// The cached stats of every whitelisted player by server name
+type StatsCache = HashMap<String, Arc<Mutex<Option<ServerStats>>>>;
+
+fn stats_cache() -> &'static std::sync::Mutex<StatsCache> {
+    static STATS_CACHE: OnceLock<std::sync::Mutex<StatsCache>> = OnceLock::new();
+    STATS_CACHE.get_or_init(|| std::sync::Mutex::new(HashMap::new()))
+}
+
+pub(super) fn create_command() -> CreateCommand {
+    CreateCommand::new("mcstats")
+        .description("Shows a leaderboard of a Minecraft statistic")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "stat",
+                "e.g. blocks_mined, mined:obsidian, crafted:rail or custom:aviate_one_cm",
+            )
+            .required(true),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "server",
+            "The server name (default SMP)",
+        ))
+}
+
+/// Posts a leaderboard, from stats that are refreshed when the command is used and the cached stats
+/// are older than `STATS_CACHE_TTL`.
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    let option = |name: &str| {
+        command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_str())
+    };
+    let stat = option("stat").unwrap_or_default();
+    let config = config::get();
+    let server = server_or_default_smp(&config, option("server"));
+    let (Some(server), Some(stat)) = (server, StatPath::parse(stat)) else {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(format!(
+                            "Unknown server or invalid stat. Stats look like `mined`, `mined:obsidian` or one of {}",
+                            STAT_ALIASES
+                                .iter()
+                                .map(|(alias, _)| format!("`{alias}`"))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ))
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+        )
+        .await?;
+
+    let response = match leaderboard(data, server, &stat).await {
+        Ok(embed) => EditInteractionResponse::new().embed(embed),
+        Err(err) => EditInteractionResponse::new().content(format!("Failed to fetch stats: {err}")),
+    };
+    command.edit_response(&ctx.http, response).await?;
+    Ok(())
+}
+
+async fn leaderboard(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    stat: &StatPath,
+) -> crate::Result<CreateEmbed> {
+    let server_cache = stats_cache()
+        .lock()
+        .unwrap()
+        .entry(server.name.clone())
+        .or_default()
+        .clone();
+    // Holding the lock while fetching makes concurrent requests wait for the same fetch
+    let mut server_cache = server_cache.lock().await;
+    if server_cache
+        .as_ref()
+        .is_none_or(|stats| stats.fetched_at + STATS_CACHE_TTL <= Utc::now())
+    {
+        *server_cache = Some(fetch_stats(data, server).await?);
+    }
+    let stats = server_cache.as_ref().unwrap();
+
+    let mut ranking: Vec<_> = stats
+        .players
+        .iter()
+        .map(|(name, player_stats)| (name, stat.value(player_stats)))
+        .filter(|&(_, value)| value > 0)
+        .collect();
+    ranking.sort_by(|(name_a, value_a), (name_b, value_b)| {
+        value_b.cmp(value_a).then_with(|| name_a.cmp(name_b))
+    });
+
+    let description = if ranking.is_empty() {
+        "Nobody has this stat yet".to_owned()
+    } else {
+        ranking
+            .iter()
+            .take(LEADERBOARD_SIZE)
+            .enumerate()
+            .map(|(rank, (name, value))| {
+                format!("**{}.** {}: {}", rank + 1, name, stat.format(*value))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    Ok(CreateEmbed::new()
+        .title(format!("{} on {}", stat, server.display_name))
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!(
+            "{} players, updated {}",
+            stats.players.len(),
+            stats.fetched_at.format("%H:%M UTC")
+        ))))
+}
+
+/// Downloads the stats of every whitelisted player who has a stats file. Files that can't be read
+/// are skipped, so that one broken file doesn't break the leaderboard.
+async fn fetch_stats(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+) -> crate::Result<ServerStats> {
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let whitelist: Vec<Player> =
+        serde_json::from_str(&maintenance::read_whitelist(server, &ptero_server).await?)?;
+    let stats_files: Vec<_> = ptero_server
+        .list_files(STATS_DIRECTORY)
+        .await?
+        .into_iter()
+        .map(|file| file.name)
+        .collect();
+
+    let players = stream::iter(
+        whitelist
+            .into_iter()
+            .filter(|player| stats_files.contains(&format!("{}.json", player.uuid.hyphenated()))),
+    )
+    .map(|player| {
+        let ptero_server = &ptero_server;
+        async move {
+            let stats = async {
+                let json = ptero_server
+                    .file_contents_text(format!(
+                        "{}/{}.json",
+                        STATS_DIRECTORY,
+                        player.uuid.hyphenated()
+                    ))
+                    .await?;
+                let stats_file: StatsFile = serde_json::from_str(&json)?;
+                Ok::<_, crate::Error>(stats_file.stats)
+            };
+            match stats.await {
+                Ok(stats) => Some((player.name, stats)),
+                Err(err) => {
+                    warn!(
+                        "Skipping stats of {} on {}: {}",
+                        player.name, server.name, err
+                    );
+                    None
+                }
+            }
+        }
+    })
+    .buffer_unordered(CONCURRENT_DOWNLOADS)
+    .filter_map(future::ready)
+    .collect()
+    .await;
+
+    Ok(ServerStats {
+        fetched_at: Utc::now(),
+        players,
+    })
+}
+
+struct ServerStats {
+    fetched_at: DateTime<Utc>,
+    players: Vec<(String, PlayerStats)>,
+}
+
+/// Stat values by stat type (e.g. `minecraft:mined`), then by stat (e.g. `minecraft:stone`)
+type PlayerStats = HashMap<String, HashMap<String, u64>>;
+
+#[derive(Deserialize)]
+struct StatsFile {
+    #[serde(default)]
+    stats: PlayerStats,
+}
+
+/// A stat type, optionally narrowed down to one stat of that type.
+struct StatPath {
+    stat_type: String,
+    stat: Option<String>,
+}
+
+impl StatPath {
+    fn parse(stat: &str) -> Option<StatPath> {
+        let stat = stat.trim().to_ascii_lowercase();
+        let stat = STAT_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == stat)
+            .map_or(stat.as_str(), |(_, path)| path);
+        let (stat_type, stat) = match stat.split_once(':') {
+            Some((stat_type, stat)) => (stat_type, Some(stat)),
+            None => (stat, None),
+        };
+        if stat_type.is_empty() || stat.is_some_and(str::is_empty) {
+            return None;
+        }
+        Some(StatPath {
+            stat_type: format!("minecraft:{stat_type}"),
+            stat: stat.map(|stat| format!("minecraft:{stat}")),
+        })
+    }
+
+    /// Returns the value of the stat, or the sum of all stats of the type if no stat was given.
+    fn value(&self, player_stats: &PlayerStats) -> u64 {
+        let Some(stats) = player_stats.get(&self.stat_type) else {
+            return 0;
+        };
+        match &self.stat {
+            Some(stat) => stats.get(stat).copied().unwrap_or(0),
+            None => stats.values().sum(),
+        }
+    }
+
+    fn format(&self, value: u64) -> String {
+        let stat = self.stat.as_deref().unwrap_or_default();
+        if stat.ends_with("_one_cm") {
+            format!("{:.1} km", value as f64 / 100_000.0)
+        } else if stat == "minecraft:play_time" || stat.starts_with("minecraft:time_since") {
+            format!("{:.1} hours", value as f64 / 20.0 / 60.0 / 60.0)
+        } else {
+            value.to_string()
+        }
+    }
+}
+
+impl std::fmt::Display for StatPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let strip = |name: &str| name.trim_start_matches("minecraft:").replace('_', " ");
+        match &self.stat {
+            Some(stat) => write!(f, "{} {}", strip(&self.stat_type), strip(stat)),
+            None => write!(f, "{}", strip(&self.stat_type)),
+        }
+    }
+}
@@ -1,26 +1,42 @@
+pub(crate) mod announce;
 mod april_fools_channel;
 mod brainfuck;
 mod chess;
 mod commands;
+mod console;
 mod counter;
-mod guild_storage;
+pub(crate) mod guild_storage;
+mod link;
+mod logs;
+mod mcstats;
 mod mood;
+mod panel_email;
 mod permanent_latest;
+mod playtime;
+mod properties;
 mod reaction_role_toggle;
+pub(crate) mod reports;
 mod role;
 mod roletoggle;
+mod schematic;
 mod social_credit;
+mod stats;
 mod storage;
 mod support;
 mod update_copy;
 mod welcome_message;
+mod world_data;
 
-use crate::config;
+use crate::config::Config;
 use crate::discord_bot::april_fools_channel::{
     get_april_fools_channel, AprilFoolsChannel, AprilFoolsMessageContext,
 };
 use crate::discord_bot::guild_storage::GuildStorage;
-use crate::pterodactyl::{tellraw, PterodactylChatBridge, PterodactylServer};
+use crate::pterodactyl::{
+    role_whitelist, tellraw, PterodactylChatBridge, PterodactylPanels, PterodactylServer,
+    PterodactylServerCategory,
+};
+use crate::{config, ProtobotData};
 use async_trait::async_trait;
 use dashmap::{DashMap, Entry};
 use futures::future::try_join_all;
@@ -34,10 +50,10 @@ use serenity::client::{Context, EventHandler};
 use serenity::http::Http;
 use serenity::model::application::{CommandInteraction, Interaction};
 use serenity::model::channel::{Message, Reaction};
-use serenity::model::event::MessageUpdateEvent;
+use serenity::model::event::{GuildMemberUpdateEvent, MessageUpdateEvent};
 use serenity::model::gateway::Ready;
 use serenity::model::guild::Member;
-use serenity::model::id::{GuildId, UserId};
+use serenity::model::id::{GuildId, RoleId, UserId};
 use serenity::model::user::User;
 use serenity::prelude::GatewayIntents;
 use serenity::Client;
@@ -49,28 +65,108 @@ pub(crate) type Handle = Arc<Http>;
 
 struct Handler {
     webhook_cache: Arc<DashMap<String, Webhook>>,
-    pterodactyl: Arc<pterodactyl_api::client::Client>,
+    pterodactyl: Arc<PterodactylPanels>,
     own_id: RwLock<Option<UserId>>,
 }
 
+impl Handler {
+    fn protobot_data(&self, ctx: &Context) -> ProtobotData {
+        ProtobotData {
+            discord_handle: ctx.http.clone(),
+            pterodactyl: self.pterodactyl.clone(),
+        }
+    }
+
+    fn sync_role_whitelist(&self, ctx: &Context, user_id: UserId, roles: Vec<RoleId>) {
+        let data = self.protobot_data(ctx);
+        tokio::runtime::Handle::current().spawn(async move {
+            if let Err(err) = role_whitelist::sync_member(
+                &data,
+                &role_whitelist::role_whitelist_sender(),
+                user_id,
+                &roles,
+            )
+            .await
+            {
+                error!("Failed to sync role whitelist of user {}: {}", user_id, err);
+            }
+        });
+    }
+}
+
 async fn create_commands(ctx: &Context, guild_id: GuildId) -> serenity::Result<()> {
     guild_id
         .set_commands(
             &ctx.http,
-            vec![
+            [
                 CreateCommand::new("hello").description("A test command"),
                 CreateCommand::new("update_copy").description("Updates the SMP copy"),
-            ],
+            ]
+            .into_iter()
+            .chain(link::create_commands())
+            .chain(playtime::create_commands())
+            .chain(world_data::create_commands())
+            .chain([
+                panel_email::create_command(),
+                announce::create_command(),
+                stats::create_command(),
+                logs::create_command(),
+                mcstats::create_command(),
+                schematic::create_command(),
+                properties::create_command(),
+            ])
+            .chain(console::create_commands())
+            .collect(),
         )
         .await?;
     Ok(())
 }
 
-#[allow(clippy::single_match)]
+pub(crate) async fn check_panel_access(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> serenity::Result<bool> {
+    if command.member.as_ref().map(|member| {
+        member
+            .roles
+            .contains(&config::get().special_roles.panel_access)
+    }) == Some(true)
+    {
+        return Ok(true);
+    }
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content("You do not have permission to use that command"),
+            ),
+        )
+        .await?;
+    Ok(false)
+}
+
+/// Finds the server with the given name, or the first SMP server if no name was given.
+fn server_or_default_smp<'a>(
+    config: &'a Config,
+    server_name: Option<&str>,
+) -> Option<&'a PterodactylServer> {
+    match server_name {
+        Some(server_name) => config
+            .pterodactyl_servers
+            .iter()
+            .find(|server| server.name == server_name),
+        None => config
+            .pterodactyl_servers(PterodactylServerCategory::SMP)
+            .next(),
+    }
+}
+
 async fn process_command(
     ctx: &Context,
     command: CommandInteraction,
-    pterodactyl: &pterodactyl_api::client::Client,
+    data: &ProtobotData,
 ) -> serenity::Result<()> {
     match &command.data.name[..] {
         "hello" => {
@@ -84,21 +180,7 @@ async fn process_command(
                 .await?;
         }
         "update_copy" => {
-            if command.member.as_ref().map(|member| {
-                member
-                    .roles
-                    .contains(&config::get().special_roles.panel_access)
-            }) != Some(true)
-            {
-                command
-                    .create_response(
-                        &ctx.http,
-                        CreateInteractionResponse::Message(
-                            CreateInteractionResponseMessage::new()
-                                .content("You do not have permission to use that command"),
-                        ),
-                    )
-                    .await?;
+            if !check_panel_access(ctx, &command).await? {
                 return Ok(());
             }
             command
@@ -109,7 +191,7 @@ async fn process_command(
                     ),
                 )
                 .await?;
-            match update_copy::run(ctx, &command, pterodactyl).await {
+            match update_copy::run(ctx, &command, &data.pterodactyl).await {
                 Err(crate::Error::Serenity(err)) => return Err(*err),
                 Err(err) => {
                     command
@@ -123,6 +205,70 @@ async fn process_command(
                 Ok(()) => {}
             }
         }
+        "panel_email" => match panel_email::run(ctx, &command).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run panel_email command: {}", err),
+            Ok(()) => {}
+        },
+        "stats" => match stats::run(ctx, &command).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run stats command: {}", err),
+            Ok(()) => {}
+        },
+        "announce" => match announce::run(ctx, &command, data).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run announce command: {}", err),
+            Ok(()) => {}
+        },
+        "logs" => match logs::run(ctx, &command, data).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run logs command: {}", err),
+            Ok(()) => {}
+        },
+        "seen" | "playtime" | "playtime_top" => match playtime::run(ctx, &command).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run {} command: {}", command.data.name, err),
+            Ok(()) => {}
+        },
+        "mcstats" => match mcstats::run(ctx, &command, data).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run mcstats command: {}", err),
+            Ok(()) => {}
+        },
+        "properties" => match properties::run(ctx, &command, data).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run properties command: {}", err),
+            Ok(()) => {}
+        },
+        "schematic" => match schematic::run(ctx, &command, data).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run schematic command: {}", err),
+            Ok(()) => {}
+        },
+        "scoreboard" | "player" => match world_data::run(ctx, &command, data).await {
+            Err(crate::Error::Serenity(err)) => return Err(*err),
+            Err(err) => error!("Failed to run {} command: {}", command.data.name, err),
+            Ok(()) => {}
+        },
+        "link" | "unlink" => {
+            let result = if command.data.name == "link" {
+                link::link(ctx, &command, data).await
+            } else {
+                link::unlink(ctx, &command, data).await
+            };
+            match result {
+                Err(crate::Error::Serenity(err)) => return Err(*err),
+                Err(err) => error!("Failed to run {} command: {}", command.data.name, err),
+                Ok(()) => {}
+            }
+        }
+        name if console::is_console_command(name) => {
+            match console::run(ctx, &command, data).await {
+                Err(crate::Error::Serenity(err)) => return Err(*err),
+                Err(err) => error!("Failed to run console command: {}", err),
+                Ok(()) => {}
+            }
+        }
         _ => {}
     }
     Ok(())
@@ -131,7 +277,7 @@ async fn process_command(
 async fn process_chatbridge(
     ctx: Context,
     webhook_cache: &DashMap<String, Webhook>,
-    pterodactyl: &pterodactyl_api::client::Client,
+    pterodactyl: &PterodactylPanels,
     chat_bridge: &PterodactylChatBridge,
     new_message: &Message,
 ) -> crate::Result<()> {
@@ -146,6 +292,7 @@ async fn process_chatbridge(
                     .iter()
                     .find(|server| &server.name == server_name)
             })
+            .filter(|server| server.category.is_bridgeable())
             .map(|server| send_chatbridge_message(&ctx, pterodactyl, server, new_message)),
     )
     .await?;
@@ -169,11 +316,11 @@ async fn process_chatbridge(
 
 async fn send_chatbridge_message(
     ctx: &Context,
-    pterodactyl: &pterodactyl_api::client::Client,
+    pterodactyl: &PterodactylPanels,
     server: &PterodactylServer,
     message: &Message,
 ) -> crate::Result<()> {
-    let ptero_server = pterodactyl.get_server(&server.id);
+    let ptero_server = pterodactyl.get_server(server)?;
 
     let sanitized_message = message.content_safe(ctx);
     if !sanitized_message.is_empty() {
@@ -288,6 +435,10 @@ impl EventHandler for Handler {
         user: User,
         member_data_if_available: Option<Member>,
     ) {
+        if guild_id == config::get().guild_id {
+            self.sync_role_whitelist(&ctx, user.id, Vec::new());
+        }
+
         if let Some(join_log_channel) = GuildStorage::get(guild_id).await.join_log_channel {
             if let Err(err) = join_log_channel
                 .send_message(
@@ -306,6 +457,18 @@ impl EventHandler for Handler {
         }
     }
 
+    async fn guild_member_update(
+        &self,
+        ctx: Context,
+        _old_if_available: Option<Member>,
+        _new: Option<Member>,
+        event: GuildMemberUpdateEvent,
+    ) {
+        if event.guild_id == config::get().guild_id {
+            self.sync_role_whitelist(&ctx, event.user.id, event.roles);
+        }
+    }
+
     async fn message(&self, ctx: Context, new_message: Message) {
         let guild_id = match new_message.guild_id {
             Some(guild_id) => guild_id,
@@ -321,6 +484,7 @@ impl EventHandler for Handler {
                 Command(&'a str),
                 IncCounter(&'a str),
                 PermanentLatest,
+                Schematic,
                 AprilFools(&'static dyn AprilFoolsChannel),
             }
 
@@ -353,6 +517,8 @@ impl EventHandler for Handler {
                                 } else if let Some(counter) = new_message.content.strip_suffix("++")
                                 {
                                     MessageHandling::IncCounter(counter)
+                                } else if schematic::has_litematic(&new_message) {
+                                    MessageHandling::Schematic
                                 } else {
                                     return;
                                 }
@@ -382,6 +548,7 @@ impl EventHandler for Handler {
                 MessageHandling::PermanentLatest => {
                     permanent_latest::on_message(guild_id, ctx, &new_message).await
                 }
+                MessageHandling::Schematic => schematic::on_message(ctx, &new_message).await,
                 MessageHandling::AprilFools(april_fools) => {
                     april_fools_channel::on_message(
                         april_fools,
@@ -487,20 +654,51 @@ impl EventHandler for Handler {
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            let pterodactyl = self.pterodactyl.clone();
-            tokio::runtime::Handle::current().spawn(async move {
-                if let Err(err) = process_command(&ctx, command, &pterodactyl).await {
-                    error!("Failed to process command: {}", err);
-                }
-            });
+        match interaction {
+            Interaction::Command(command) => {
+                let data = self.protobot_data(&ctx);
+                tokio::runtime::Handle::current().spawn(async move {
+                    if let Err(err) = process_command(&ctx, command, &data).await {
+                        error!("Failed to process command: {}", err);
+                    }
+                });
+            }
+            Interaction::Component(component)
+                if component.data.custom_id == reports::CLAIM_BUTTON_ID
+                    || component.data.custom_id == reports::RESOLVE_BUTTON_ID =>
+            {
+                tokio::runtime::Handle::current().spawn(async move {
+                    if let Err(err) = reports::on_button(&ctx, &component).await {
+                        error!("Failed to process report button: {}", err);
+                    }
+                });
+            }
+            Interaction::Component(component)
+                if component
+                    .data
+                    .custom_id
+                    .starts_with(properties::RESTART_BUTTON_PREFIX) =>
+            {
+                let data = self.protobot_data(&ctx);
+                tokio::runtime::Handle::current().spawn(async move {
+                    if let Err(err) = properties::on_restart_button(&ctx, &component, &data).await {
+                        error!("Failed to process restart button: {}", err);
+                    }
+                });
+            }
+            Interaction::Modal(modal) if modal.data.custom_id == panel_email::MODAL_ID => {
+                tokio::runtime::Handle::current().spawn(async move {
+                    if let Err(err) = panel_email::on_modal_submit(&ctx, &modal).await {
+                        error!("Failed to process panel email: {}", err);
+                    }
+                });
+            }
+            _ => {}
         }
     }
 }
 
-pub(crate) async fn create_client(
-    pterodactyl: Arc<pterodactyl_api::client::Client>,
-) -> crate::Result<Client> {
+pub(crate) async fn create_client(pterodactyl: Arc<PterodactylPanels>) -> crate::Result<Client> {
     let intents = GatewayIntents::GUILDS
         | GatewayIntents::GUILD_MEMBERS
         | GatewayIntents::GUILD_MESSAGES
@@ -0,0 +1,95 @@
+use crate::config;
+use crate::discord_bot::guild_storage::GuildStorage;
+use serenity::all::{ActionRowComponent, InputTextStyle, ModalInteraction};
+use serenity::builder::{
+    CreateActionRow, CreateCommand, CreateInputText, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateModal,
+};
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+
+pub(super) const MODAL_ID: &str = "panel_email";
+const EMAIL_INPUT_ID: &str = "email";
+
+pub(super) fn create_command() -> CreateCommand {
+    CreateCommand::new("panel_email")
+        .description("Sets the email used for your Pterodactyl panel account")
+}
+
+pub(super) async fn run(ctx: &Context, command: &CommandInteraction) -> crate::Result<()> {
+    let mut input = CreateInputText::new(InputTextStyle::Short, "Panel email", EMAIL_INPUT_ID)
+        .placeholder("steve@example.com")
+        .max_length(254);
+    if let Some(email) = GuildStorage::get(config::get().guild_id)
+        .await
+        .panel_emails
+        .get(&command.user.id)
+    {
+        input = input.value(email);
+    }
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Modal(
+                CreateModal::new(MODAL_ID, "Pterodactyl panel email")
+                    .components(vec![CreateActionRow::InputText(input)]),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+pub(super) async fn on_modal_submit(ctx: &Context, modal: &ModalInteraction) -> crate::Result<()> {
+    let email = modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == EMAIL_INPUT_ID => {
+                input.value.as_deref()
+            }
+            _ => None,
+        })
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    let message = if is_plausible_email(&email) {
+        let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+        storage.panel_emails.insert(modal.user.id, email.clone());
+        storage.save().await;
+        format!(
+            "Your panel email has been set to {email}. It will be used from the next perms sync."
+        )
+    } else {
+        format!("{email} is not a valid email address")
+    };
+
+    modal
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(message)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+fn is_plausible_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.contains('@')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !email.contains(char::is_whitespace)
+        }
+        None => false,
+    }
+}
@@ -0,0 +1,182 @@
+use crate::config;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::playtime::format_playtime;
+use chrono::Utc;
+use serenity::builder::{
+    CreateCommand, CreateCommandOption, CreateInteractionResponse, CreateInteractionResponseMessage,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+use std::collections::HashMap;
+
+const LEADERBOARD_SIZE: usize = 10;
+
+pub(super) fn create_commands() -> [CreateCommand; 3] {
+    let player_option = || {
+        CreateCommandOption::new(
+            CommandOptionType::String,
+            "player",
+            "The Minecraft username",
+        )
+        .required(true)
+    };
+    [
+        CreateCommand::new("seen")
+            .description("Shows when a player was last online")
+            .add_option(player_option()),
+        CreateCommand::new("playtime")
+            .description("Shows how long a player has played on each server")
+            .add_option(player_option()),
+        CreateCommand::new("playtime_top")
+            .description("Shows the players with the most playtime")
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "server",
+                "Only count playtime on this server",
+            )),
+    ]
+}
+
+pub(super) async fn run(ctx: &Context, command: &CommandInteraction) -> crate::Result<()> {
+    let option = |name: &str| {
+        command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_str())
+    };
+    let message = match &command.data.name[..] {
+        "seen" => seen(option("player").unwrap_or_default()).await,
+        "playtime" => playtime(option("player").unwrap_or_default()).await,
+        _ => leaderboard(option("server")).await,
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new().content(message),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+async fn seen(player: &str) -> String {
+    let config = config::get();
+    let storage = GuildStorage::get(config.guild_id).await;
+    let key = player.to_ascii_lowercase();
+    let activities: Vec<_> = config
+        .pterodactyl_servers
+        .iter()
+        .filter_map(|server| {
+            let activity = storage.player_activity.get(&server.name)?.get(&key)?;
+            Some((server, activity))
+        })
+        .collect();
+
+    let online: Vec<_> = activities
+        .iter()
+        .filter(|(_, activity)| activity.is_online())
+        .map(|(server, _)| server.display_name.as_str())
+        .collect();
+    if !online.is_empty() {
+        return format!(
+            "{} is online on {}",
+            activities[0].1.name,
+            online.join(", ")
+        );
+    }
+
+    match activities
+        .iter()
+        .max_by_key(|(_, activity)| activity.last_seen)
+    {
+        Some((server, activity)) => format!(
+            "{} was last seen on {} <t:{}:R>",
+            activity.name, server.display_name, activity.last_seen
+        ),
+        None => format!("{player} has never been seen"),
+    }
+}
+
+async fn playtime(player: &str) -> String {
+    let config = config::get();
+    let storage = GuildStorage::get(config.guild_id).await;
+    let key = player.to_ascii_lowercase();
+    let now = Utc::now();
+    let mut playtimes: Vec<_> = config
+        .pterodactyl_servers
+        .iter()
+        .filter_map(|server| {
+            let activity = storage.player_activity.get(&server.name)?.get(&key)?;
+            Some((server, activity))
+        })
+        .collect();
+    if playtimes.is_empty() {
+        return format!("{player} has never been seen");
+    }
+    playtimes.sort_by_key(|(_, activity)| -activity.playtime_seconds(now));
+
+    let total: i64 = playtimes
+        .iter()
+        .map(|(_, activity)| activity.playtime_seconds(now))
+        .sum();
+    let mut message = format!(
+        "**{}** has played for {} in total",
+        playtimes[0].1.name,
+        format_playtime(total)
+    );
+    for (server, activity) in playtimes {
+        message += &format!(
+            "\n{}: {} over {} sessions",
+            server.display_name,
+            format_playtime(activity.playtime_seconds(now)),
+            activity.sessions
+        );
+    }
+    message
+}
+
+async fn leaderboard(server_name: Option<&str>) -> String {
+    let config = config::get();
+    let server = match server_name {
+        Some(server_name) => match config
+            .pterodactyl_servers
+            .iter()
+            .find(|server| server.name == server_name)
+        {
+            Some(server) => Some(server),
+            None => return format!("Unknown server {server_name}"),
+        },
+        None => None,
+    };
+
+    let storage = GuildStorage::get(config.guild_id).await;
+    let now = Utc::now();
+    let mut playtimes: HashMap<&str, (&str, i64)> = HashMap::new();
+    for (activity_server, activities) in &storage.player_activity {
+        if server.is_some_and(|server| server.name != *activity_server) {
+            continue;
+        }
+        for (key, activity) in activities {
+            let playtime = playtimes.entry(key).or_insert((&activity.name, 0));
+            playtime.1 += activity.playtime_seconds(now);
+        }
+    }
+    let mut playtimes: Vec<_> = playtimes.into_values().collect();
+    playtimes.sort_by_key(|&(_, seconds)| -seconds);
+
+    let mut message = match server {
+        Some(server) => format!("**Playtime leaderboard for {}**", server.display_name),
+        None => "**Playtime leaderboard**".to_owned(),
+    };
+    if playtimes.is_empty() {
+        message += "\nNobody has played yet";
+    }
+    for (rank, (name, seconds)) in playtimes.into_iter().take(LEADERBOARD_SIZE).enumerate() {
+        message += &format!("\n{}. {}: {}", rank + 1, name, format_playtime(seconds));
+    }
+    message
+}
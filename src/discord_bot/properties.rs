@@ -0,0 +1,406 @@
+use crate::discord_bot::check_panel_access;
+use crate::pterodactyl::PterodactylServer;
+use crate::{config, ProtobotData};
+use log::info;
+use pterodactyl_api::client::PowerSignal;
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use serenity::client::Context;
+use serenity::model::application::{
+    ButtonStyle, CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+    ComponentInteraction,
+};
+
+const PROPERTIES_FILE: &str = "server.properties";
+/// Followed by the name of the server to restart
+pub(super) const RESTART_BUTTON_PREFIX: &str = "properties_restart:";
+
+/// The properties that can be read and changed from Discord, and the values they accept.
+const EDITABLE_PROPERTIES: &[(&str, PropertyType)] = &[
+    ("allow-flight", PropertyType::Bool),
+    (
+        "difficulty",
+        PropertyType::Choice(&["peaceful", "easy", "normal", "hard"]),
+    ),
+    (
+        "entity-broadcast-range-percentage",
+        PropertyType::Int(10, 1000),
+    ),
+    ("max-players", PropertyType::Int(1, 1000)),
+    ("motd", PropertyType::Text(150)),
+    ("pvp", PropertyType::Bool),
+    ("simulation-distance", PropertyType::Int(3, 32)),
+    ("spawn-protection", PropertyType::Int(0, 1000)),
+    ("view-distance", PropertyType::Int(3, 32)),
+];
+
+#[derive(Debug, Copy, Clone)]
+enum PropertyType {
+    Bool,
+    /// An integer within an inclusive range
+    Int(i64, i64),
+    Choice(&'static [&'static str]),
+    /// Text of up to this many characters
+    Text(usize),
+}
+
+impl PropertyType {
+    /// Returns the value as it should be written, or why it is invalid.
+    fn parse(self, value: &str) -> Result<String, String> {
+        let value = value.trim();
+        match self {
+            PropertyType::Bool => match value {
+                "true" | "false" => Ok(value.to_owned()),
+                _ => Err("must be true or false".to_owned()),
+            },
+            PropertyType::Int(min, max) => match value.parse::<i64>() {
+                Ok(int) if (min..=max).contains(&int) => Ok(int.to_string()),
+                _ => Err(format!("must be a whole number from {min} to {max}")),
+            },
+            PropertyType::Choice(choices) => {
+                let value = value.to_lowercase();
+                if choices.contains(&&value[..]) {
+                    Ok(value)
+                } else {
+                    Err(format!("must be one of {}", choices.join(", ")))
+                }
+            }
+            PropertyType::Text(max_length) => {
+                if value.chars().count() > max_length {
+                    Err(format!("must be at most {max_length} characters"))
+                } else if value.contains(['\n', '\r']) {
+                    Err("must be a single line".to_owned())
+                } else {
+                    Ok(value.to_owned())
+                }
+            }
+        }
+    }
+}
+
+pub(super) fn create_command() -> CreateCommand {
+    let server_option = || {
+        CreateCommandOption::new(CommandOptionType::String, "server", "The server name")
+            .required(true)
+    };
+    let key_option = || {
+        EDITABLE_PROPERTIES.iter().fold(
+            CreateCommandOption::new(CommandOptionType::String, "key", "The property")
+                .required(true),
+            |option, (key, _)| option.add_string_choice(*key, *key),
+        )
+    };
+    CreateCommand::new("properties")
+        .description("Views and changes server.properties")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "get",
+                "Shows the value of a property",
+            )
+            .add_sub_option(server_option())
+            .add_sub_option(key_option()),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "set",
+                "Changes a property, which takes effect when the server restarts",
+            )
+            .add_sub_option(server_option())
+            .add_sub_option(key_option())
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "value", "The new value")
+                    .required(true),
+            ),
+        )
+}
+
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    let [CommandDataOption {
+        name,
+        value: CommandDataOptionValue::SubCommand(options),
+        ..
+    }] = &command.data.options[..]
+    else {
+        return Ok(());
+    };
+    let option = |name: &str| {
+        options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_str())
+            .unwrap_or_default()
+    };
+    if name == "set" && !check_panel_access(ctx, command).await? {
+        return Ok(());
+    }
+
+    let config = config::get();
+    let server_name = option("server");
+    let Some(server) = config
+        .pterodactyl_servers
+        .iter()
+        .find(|server| server.name == server_name)
+        .filter(|server| config.category_info(server.category).minecraft)
+    else {
+        return respond(
+            ctx,
+            command,
+            error_response(format!("Unknown Minecraft server {server_name}")),
+        )
+        .await;
+    };
+    let key = option("key");
+    let Some(&(key, property_type)) = EDITABLE_PROPERTIES
+        .iter()
+        .find(|(editable_key, _)| *editable_key == key)
+    else {
+        return respond(
+            ctx,
+            command,
+            error_response(format!("{key} can't be edited from Discord")),
+        )
+        .await;
+    };
+
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let mut properties =
+        Properties::parse(&ptero_server.file_contents_text(PROPERTIES_FILE).await?);
+    let old_value = properties.get(key);
+    let response = match &name[..] {
+        "get" => match old_value {
+            Some(value) => CreateInteractionResponseMessage::new()
+                .content(format!("{key} on {} is `{value}`", server.display_name)),
+            None => CreateInteractionResponseMessage::new().content(format!(
+                "{key} is not set on {}, so it has its default value",
+                server.display_name
+            )),
+        },
+        "set" => {
+            let value = match property_type.parse(option("value")) {
+                Ok(value) => value,
+                Err(message) => {
+                    return respond(ctx, command, error_response(format!("{key} {message}"))).await
+                }
+            };
+            properties.set(key, &value);
+            ptero_server
+                .write_file(PROPERTIES_FILE, properties.to_string())
+                .await?;
+            info!(
+                "{} set {} to {} on {}",
+                command.user.name, key, value, server.name
+            );
+            CreateInteractionResponseMessage::new()
+                .content(format!(
+                    "Changed {key} on {} from `{}` to `{value}`. It takes effect when the server restarts",
+                    server.display_name,
+                    old_value.as_deref().unwrap_or("default"),
+                ))
+                .components(vec![restart_button(server, false)])
+        }
+        _ => return Ok(()),
+    };
+    respond(ctx, command, response).await
+}
+
+fn restart_button(server: &PterodactylServer, restarting: bool) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(format!(
+        "{RESTART_BUTTON_PREFIX}{}",
+        server.name
+    ))
+    .label(if restarting {
+        "Restarting"
+    } else {
+        "Restart now"
+    })
+    .style(ButtonStyle::Danger)
+    .disabled(restarting)])
+}
+
+/// Handles the restart button offered after changing a property.
+pub(super) async fn on_restart_button(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    let config = config::get();
+    let has_access = component
+        .member
+        .as_ref()
+        .is_some_and(|member| member.roles.contains(&config.special_roles.panel_access));
+    let server = component
+        .data
+        .custom_id
+        .strip_prefix(RESTART_BUTTON_PREFIX)
+        .and_then(|server_name| {
+            config
+                .pterodactyl_servers
+                .iter()
+                .find(|server| server.name == server_name)
+        });
+    let Some(server) = server.filter(|_| has_access) else {
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("You do not have permission to restart that server")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    data.pterodactyl
+        .get_server(server)?
+        .send_power_signal(PowerSignal::Restart)
+        .await?;
+    info!("{} restarted {}", component.user.name, server.name);
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(
+                CreateInteractionResponseMessage::new()
+                    .components(vec![restart_button(server, true)]),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+fn error_response(message: String) -> CreateInteractionResponseMessage {
+    CreateInteractionResponseMessage::new()
+        .content(message)
+        .ephemeral(true)
+}
+
+async fn respond(
+    ctx: &Context,
+    command: &CommandInteraction,
+    response: CreateInteractionResponseMessage,
+) -> crate::Result<()> {
+    command
+        .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+        .await?;
+    Ok(())
+}
+
+/// A properties file, kept line by line so that comments and ordering survive editing it.
+struct Properties {
+    lines: Vec<String>,
+}
+
+impl Properties {
+    fn parse(contents: &str) -> Self {
+        Properties {
+            lines: contents.lines().map(str::to_owned).collect(),
+        }
+    }
+
+    /// Finds the line setting a key, and where its value starts in that line.
+    fn find(&self, key: &str) -> Option<(usize, usize)> {
+        self.lines.iter().enumerate().find_map(|(index, line)| {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with(['#', '!']) {
+                return None;
+            }
+            let separator = trimmed.find(['=', ':'])?;
+            if trimmed[..separator].trim_end() != key {
+                return None;
+            }
+            let value_start = line.len() - trimmed.len() + separator + 1;
+            let value_start =
+                value_start + (line[value_start..].len() - line[value_start..].trim_start().len());
+            Some((index, value_start))
+        })
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        let (index, value_start) = self.find(key)?;
+        Some(unescape(&self.lines[index][value_start..]))
+    }
+
+    /// Changes the value of a key in place, or adds it to the end if it isn't set.
+    fn set(&mut self, key: &str, value: &str) {
+        let value = escape(value);
+        match self.find(key) {
+            Some((index, value_start)) => {
+                self.lines[index].truncate(value_start);
+                self.lines[index] += &value;
+            }
+            None => self.lines.push(format!("{key}={value}")),
+        }
+    }
+}
+
+impl std::fmt::Display for Properties {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{line}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes a value the way Java writes properties files, which are read as ISO 8859-1.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for (index, char) in value.chars().enumerate() {
+        match char {
+            '\\' => escaped += "\\\\",
+            ' ' if index == 0 => escaped += "\\ ",
+            '\t' => escaped += "\\t",
+            '#' | '!' | '=' | ':' => {
+                escaped.push('\\');
+                escaped.push(char);
+            }
+            ' '..='~' => escaped.push(char),
+            _ => {
+                let mut units = [0; 2];
+                for unit in char.encode_utf16(&mut units) {
+                    escaped += &format!("\\u{unit:04X}");
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(value: &str) -> String {
+    let mut units = Vec::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            let mut buffer = [0; 2];
+            units.extend_from_slice(char.encode_utf16(&mut buffer));
+            continue;
+        }
+        match chars.next() {
+            Some('t') => units.push(u16::from(b'\t')),
+            Some('n') => units.push(u16::from(b'\n')),
+            Some('r') => units.push(u16::from(b'\r')),
+            Some('f') => units.push(0x0c),
+            Some('u') => {
+                let hex: String = chars.by_ref().take(4).collect();
+                if let Ok(unit) = u16::from_str_radix(&hex, 16) {
+                    units.push(unit);
+                }
+            }
+            Some(char) => {
+                let mut buffer = [0; 2];
+                units.extend_from_slice(char.encode_utf16(&mut buffer));
+            }
+            None => {}
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
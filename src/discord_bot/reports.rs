@@ -0,0 +1,121 @@
+use crate::{config, ProtobotData};
+use serenity::builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateInteractionResponse,
+    CreateInteractionResponseMessage, CreateMessage,
+};
+use serenity::client::Context;
+use serenity::model::application::{ButtonStyle, ComponentInteraction};
+use serenity::model::Colour;
+
+pub(super) const CLAIM_BUTTON_ID: &str = "report_claim";
+pub(super) const RESOLVE_BUTTON_ID: &str = "report_resolve";
+
+const OPEN_COLOUR: Colour = Colour::ORANGE;
+const CLAIMED_COLOUR: Colour = Colour::GOLD;
+const RESOLVED_COLOUR: Colour = Colour::DARK_GREEN;
+
+/// A request for staff sent by a player from in game.
+pub(crate) struct PlayerReport<'a> {
+    pub(crate) title: &'a str,
+    pub(crate) player: &'a str,
+    pub(crate) server: &'a str,
+    pub(crate) message: Option<String>,
+    /// Where the player was, if they could be found
+    pub(crate) location: Option<String>,
+}
+
+/// Posts a report to the staff channel, pinging the staff role. Returns whether a staff channel is
+/// configured.
+pub(crate) async fn post_report(
+    data: &ProtobotData,
+    report: PlayerReport<'_>,
+) -> crate::Result<bool> {
+    let config = config::get();
+    let Some(staff_channel) = config.special_channels.staff else {
+        return Ok(false);
+    };
+    let embed = CreateEmbed::new()
+        .title(format!("{} from {}", report.title, report.player))
+        .description(report.message.as_deref().unwrap_or("*No message*"))
+        .colour(OPEN_COLOUR)
+        .field("Server", report.server, true)
+        .field(
+            "Location",
+            report.location.as_deref().unwrap_or("Unknown"),
+            true,
+        )
+        .timestamp(serenity::model::Timestamp::now());
+    let mut message = CreateMessage::new()
+        .embed(embed)
+        .components(vec![buttons(false)]);
+    if let Some(staff_role) = config.special_roles.staff {
+        message = message.content(format!("<@&{staff_role}>"));
+    }
+    staff_channel
+        .send_message(&data.discord_handle, message)
+        .await?;
+    Ok(true)
+}
+
+fn buttons(claimed: bool) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(CLAIM_BUTTON_ID)
+            .label(if claimed { "Claimed" } else { "Claim" })
+            .style(ButtonStyle::Primary)
+            .disabled(claimed),
+        CreateButton::new(RESOLVE_BUTTON_ID)
+            .label("Resolve")
+            .style(ButtonStyle::Success),
+    ])
+}
+
+/// Handles the Claim and Resolve buttons. The report's state is kept in its message, so reports
+/// can still be handled after a restart.
+pub(super) async fn on_button(
+    ctx: &Context,
+    component: &ComponentInteraction,
+) -> crate::Result<()> {
+    let is_staff = component
+        .member
+        .as_ref()
+        .is_some_and(|member| config::get().special_roles.is_staff(&member.roles));
+    let Some(embed) = component.message.embeds.first().filter(|_| is_staff) else {
+        component
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content("You do not have permission to handle reports")
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    let embed = CreateEmbed::from(embed.clone());
+    let response = if component.data.custom_id == CLAIM_BUTTON_ID {
+        CreateInteractionResponseMessage::new()
+            .embed(embed.colour(CLAIMED_COLOUR).field(
+                "Claimed by",
+                format!("<@{}>", component.user.id),
+                false,
+            ))
+            .components(vec![buttons(true)])
+    } else {
+        CreateInteractionResponseMessage::new()
+            .embed(embed.colour(RESOLVED_COLOUR).field(
+                "Resolved by",
+                format!("<@{}>", component.user.id),
+                false,
+            ))
+            .components(Vec::new())
+    };
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::UpdateMessage(response),
+        )
+        .await?;
+    Ok(())
+}
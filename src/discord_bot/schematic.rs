@@ -0,0 +1,664 @@
+use crate::discord_bot::check_panel_access;
+use crate::nbt::{self, Tag};
+use crate::pterodactyl::PterodactylServer;
+use crate::{config, ProtobotData};
+use serenity::builder::{
+    CreateAttachment, CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{
+    CommandDataOption, CommandDataOptionValue, CommandInteraction, CommandOptionType,
+};
+use serenity::model::channel::{Attachment, Message};
+use std::collections::HashMap;
+
+/// Attachments larger than this are not downloaded
+const MAX_ATTACHMENT_SIZE: u32 = 16 * 1024 * 1024;
+/// How many schematics of one message are read
+const MAX_SCHEMATICS_PER_MESSAGE: usize = 3;
+/// How many materials are listed in the embed, the full list is in the CSV
+const MATERIALS_IN_EMBED: usize = 15;
+const SHULKER_BOX_SLOTS: u64 = 27;
+/// The file types which may be uploaded to servers
+const UPLOAD_EXTENSIONS: &[&str] = &[".litematic", ".schem", ".nbt"];
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+/// Blocks which have no item and are left out of the material list
+const IGNORED_BLOCKS: &[&str] = &[
+    "air",
+    "cave_air",
+    "void_air",
+    "bubble_column",
+    "end_gateway",
+    "end_portal",
+    "fire",
+    "moving_piston",
+    "nether_portal",
+    "piston_head",
+    "soul_fire",
+    "structure_void",
+];
+
+/// Blocks whose item has a different name
+const BLOCK_ITEMS: &[(&str, &str)] = &[
+    ("attached_melon_stem", "melon_seeds"),
+    ("attached_pumpkin_stem", "pumpkin_seeds"),
+    ("bamboo_sapling", "bamboo"),
+    ("beetroots", "beetroot_seeds"),
+    ("big_dripleaf_stem", "big_dripleaf"),
+    ("carrots", "carrot"),
+    ("cave_vines", "glow_berries"),
+    ("cave_vines_plant", "glow_berries"),
+    ("cocoa", "cocoa_beans"),
+    ("dirt_path", "dirt"),
+    ("farmland", "dirt"),
+    ("frosted_ice", "ice"),
+    ("kelp_plant", "kelp"),
+    ("lava_cauldron", "cauldron"),
+    ("melon_stem", "melon_seeds"),
+    ("pitcher_crop", "pitcher_pod"),
+    ("potatoes", "potato"),
+    ("powder_snow", "powder_snow_bucket"),
+    ("powder_snow_cauldron", "cauldron"),
+    ("pumpkin_stem", "pumpkin_seeds"),
+    ("redstone_wire", "redstone"),
+    ("sweet_berry_bush", "sweet_berries"),
+    ("tall_seagrass", "seagrass"),
+    ("torchflower_crop", "torchflower_seeds"),
+    ("tripwire", "string"),
+    ("twisting_vines_plant", "twisting_vines"),
+    ("water_cauldron", "cauldron"),
+    ("weeping_vines_plant", "weeping_vines"),
+    ("wheat", "wheat_seeds"),
+];
+
+/// Block state properties which say how many items one block is made of
+const COUNT_PROPERTIES: &[&str] = &[
+    "candles",
+    "eggs",
+    "flower_amount",
+    "layers",
+    "pickles",
+    "segment_amount",
+];
+/// More than any count property goes up to in vanilla, which untrusted files are clamped to
+const MAX_BLOCK_ITEM_COUNT: u64 = 64;
+
+pub(super) fn create_command() -> CreateCommand {
+    CreateCommand::new("schematic")
+        .description("Litematica schematic tools")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "info",
+                "Shows the size and material list of a schematic",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Attachment,
+                    "file",
+                    "The .litematic file",
+                )
+                .required(true),
+            ),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "upload",
+                "Uploads a schematic or structure to a server",
+            )
+            .add_sub_option(
+                CreateCommandOption::new(CommandOptionType::String, "server", "The server name")
+                    .required(true),
+            )
+            .add_sub_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Attachment,
+                    "file",
+                    "The .litematic, .schem or .nbt file",
+                )
+                .required(true),
+            )
+            .add_sub_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "overwrite",
+                "Replace an existing file with the same name",
+            )),
+        )
+}
+
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    let [CommandDataOption {
+        name,
+        value: CommandDataOptionValue::SubCommand(options),
+        ..
+    }] = &command.data.options[..]
+    else {
+        return Ok(());
+    };
+    let attachment = options
+        .iter()
+        .find(|option| option.name == "file")
+        .and_then(|option| option.value.as_attachment_id())
+        .and_then(|id| command.data.resolved.attachments.get(&id));
+
+    match &name[..] {
+        "info" => info(ctx, command, attachment).await,
+        "upload" => {
+            if !check_panel_access(ctx, command).await? {
+                return Ok(());
+            }
+            let option = |name: &str| {
+                options
+                    .iter()
+                    .find(|option| option.name == name)
+                    .map(|option| &option.value)
+            };
+            let server_name = option("server")
+                .and_then(CommandDataOptionValue::as_str)
+                .unwrap_or_default();
+            let overwrite = option("overwrite")
+                .and_then(CommandDataOptionValue::as_bool)
+                .unwrap_or(false);
+            upload(ctx, command, data, server_name, attachment, overwrite).await
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn info(
+    ctx: &Context,
+    command: &CommandInteraction,
+    attachment: Option<&Attachment>,
+) -> crate::Result<()> {
+    let Some(attachment) = attachment.filter(|attachment| is_litematic(attachment)) else {
+        return respond_ephemeral(ctx, command, "Please attach a .litematic file").await;
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+        )
+        .await?;
+    let response = match read_attachment(attachment).await {
+        Ok(schematic) => EditInteractionResponse::new()
+            .embed(schematic.embed())
+            .new_attachment(schematic.material_list_csv()),
+        Err(err) => EditInteractionResponse::new()
+            .content(format!("Failed to read {}: {}", attachment.filename, err)),
+    };
+    command.edit_response(&ctx.http, response).await?;
+    Ok(())
+}
+
+async fn upload(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+    server_name: &str,
+    attachment: Option<&Attachment>,
+    overwrite: bool,
+) -> crate::Result<()> {
+    let config = config::get();
+    let Some(server) = config
+        .pterodactyl_servers
+        .iter()
+        .find(|server| server.name == server_name)
+    else {
+        return respond_ephemeral(ctx, command, format!("Unknown server {server_name}")).await;
+    };
+    let Some(directory) = server.schematics_directory() else {
+        return respond_ephemeral(
+            ctx,
+            command,
+            format!(
+                "Schematic uploads to {} are not enabled",
+                server.display_name
+            ),
+        )
+        .await;
+    };
+    let Some((attachment, file_name)) = attachment.and_then(|attachment| {
+        let file_name = upload_file_name(&attachment.filename)?;
+        Some((attachment, file_name))
+    }) else {
+        return respond_ephemeral(
+            ctx,
+            command,
+            format!(
+                "Please attach a file ending in {}",
+                UPLOAD_EXTENSIONS.join(", ")
+            ),
+        )
+        .await;
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+        )
+        .await?;
+    let message =
+        match upload_file(data, server, directory, attachment, &file_name, overwrite).await {
+            Ok(message) => message,
+            Err(err) => format!("Failed to upload {file_name}: {err}"),
+        };
+    command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(message))
+        .await?;
+    Ok(())
+}
+
+/// Uploads a schematic after checking that it is valid NBT, and lists the schematics on the server.
+async fn upload_file(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    directory: &str,
+    attachment: &Attachment,
+    file_name: &str,
+    overwrite: bool,
+) -> crate::Result<String> {
+    let contents = download(attachment).await?;
+    let contents = tokio::task::spawn_blocking(move || {
+        nbt::read_file(&contents)?;
+        Ok::<_, crate::Error>(contents)
+    })
+    .await??;
+
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let list_schematics = || async {
+        match ptero_server.list_files(directory).await {
+            Ok(files) => Ok(files
+                .into_iter()
+                .filter(|file| file.is_file)
+                .map(|file| file.name)
+                .collect::<Vec<_>>()),
+            // The directory is created by the first upload
+            Err(pterodactyl_api::Error::ResourceNotFound) => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    };
+    if !overwrite
+        && list_schematics()
+            .await?
+            .iter()
+            .any(|name| name == file_name)
+    {
+        return Ok(format!(
+            "`{}/{}` already exists on {}, use `overwrite` to replace it",
+            directory, file_name, server.display_name
+        ));
+    }
+    ptero_server
+        .write_file(format!("{directory}/{file_name}"), contents)
+        .await?;
+
+    let mut files = list_schematics().await?;
+    files.sort_unstable();
+    let mut message = format!(
+        "Uploaded `{}` to `{}` on {}. Files in `{}`:",
+        file_name, directory, server.display_name, directory
+    );
+    for (index, file) in files.iter().enumerate() {
+        let line = format!("\n- {file}");
+        if message.len() + line.len() > MAX_MESSAGE_LENGTH - 20 {
+            message += &format!("\n…and {} more", files.len() - index);
+            break;
+        }
+        message += &line;
+    }
+    Ok(message)
+}
+
+/// Returns the name an attachment is uploaded as, or `None` if it is not a schematic.
+fn upload_file_name(attachment_name: &str) -> Option<String> {
+    if !UPLOAD_EXTENSIONS
+        .iter()
+        .any(|extension| attachment_name.ends_with(extension))
+    {
+        return None;
+    }
+    let file_name: String = attachment_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    (!file_name.starts_with('.')).then_some(file_name)
+}
+
+async fn respond_ephemeral(
+    ctx: &Context,
+    command: &CommandInteraction,
+    content: impl Into<String>,
+) -> crate::Result<()> {
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .ephemeral(true),
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+pub(super) fn has_litematic(message: &Message) -> bool {
+    message.attachments.iter().any(is_litematic)
+}
+
+/// Replies to a message with the info of each schematic attached to it.
+pub(super) async fn on_message(ctx: Context, message: &Message) -> crate::Result<()> {
+    for attachment in message
+        .attachments
+        .iter()
+        .filter(|attachment| is_litematic(attachment))
+        .take(MAX_SCHEMATICS_PER_MESSAGE)
+    {
+        let reply = match read_attachment(attachment).await {
+            Ok(schematic) => CreateMessage::new()
+                .embed(schematic.embed())
+                .add_file(schematic.material_list_csv()),
+            Err(err) => CreateMessage::new()
+                .content(format!("Failed to read {}: {}", attachment.filename, err)),
+        };
+        message
+            .channel_id
+            .send_message(&ctx, reply.reference_message(message))
+            .await?;
+    }
+    Ok(())
+}
+
+fn is_litematic(attachment: &Attachment) -> bool {
+    attachment.filename.ends_with(".litematic")
+}
+
+async fn download(attachment: &Attachment) -> crate::Result<Vec<u8>> {
+    if attachment.size > MAX_ATTACHMENT_SIZE {
+        return Err(crate::Error::Other(format!(
+            "schematics larger than {} MiB are not supported",
+            MAX_ATTACHMENT_SIZE / 1024 / 1024
+        )));
+    }
+    Ok(attachment.download().await?)
+}
+
+async fn read_attachment(attachment: &Attachment) -> crate::Result<Schematic> {
+    let contents = download(attachment).await?;
+    let file_name = attachment.filename.clone();
+    tokio::task::spawn_blocking(move || {
+        let (_, root) = nbt::read_file(&contents)?;
+        Schematic::read(&file_name, &root)
+    })
+    .await?
+}
+
+struct Schematic {
+    name: String,
+    author: Option<String>,
+    /// The size of the box enclosing all regions
+    size: [i64; 3],
+    region_count: usize,
+    block_count: u64,
+    /// Items sorted by count, most first
+    materials: Vec<(String, u64)>,
+}
+
+impl Schematic {
+    fn read(file_name: &str, root: &Tag) -> crate::Result<Schematic> {
+        let invalid = |reason: &str| crate::Error::Other(format!("invalid litematic: {reason}"));
+        let metadata = root.get("Metadata");
+        let regions = root
+            .get("Regions")
+            .and_then(Tag::as_compound)
+            .ok_or_else(|| invalid("no regions"))?;
+
+        let mut block_count = 0u64;
+        let mut materials: HashMap<String, u64> = HashMap::new();
+        for (region_name, region) in regions {
+            let invalid_region = |reason: &str| invalid(&format!("region {region_name} {reason}"));
+            let size =
+                read_vec3(region.get("Size")).ok_or_else(|| invalid_region("has no size"))?;
+            let volume = size
+                .iter()
+                .try_fold(1u64, |volume, &length| {
+                    volume.checked_mul(length.unsigned_abs())
+                })
+                .ok_or_else(|| invalid_region("is too large"))?;
+            let palette = region
+                .get("BlockStatePalette")
+                .and_then(Tag::as_list)
+                .ok_or_else(|| invalid_region("has no palette"))?;
+            let block_states = region
+                .get("BlockStates")
+                .and_then(Tag::as_long_array)
+                .ok_or_else(|| invalid_region("has no blocks"))?;
+
+            let counts = count_palette_entries(block_states, palette.len(), volume)
+                .ok_or_else(|| invalid_region("has invalid blocks"))?;
+            for (block_state, count) in palette.iter().zip(counts) {
+                if count == 0 {
+                    continue;
+                }
+                let items = block_items(block_state);
+                if !items.is_empty() {
+                    block_count = block_count.saturating_add(count);
+                }
+                for (item, amount) in items {
+                    let total = materials.entry(item).or_default();
+                    *total = total.saturating_add(amount.saturating_mul(count));
+                }
+            }
+        }
+        let mut materials: Vec<_> = materials.into_iter().collect();
+        materials.sort_by(|(item_a, count_a), (item_b, count_b)| {
+            count_b.cmp(count_a).then_with(|| item_a.cmp(item_b))
+        });
+
+        let metadata_str = |key: &str| {
+            metadata
+                .and_then(|metadata| metadata.get(key))
+                .and_then(Tag::as_str)
+                .filter(|value| !value.is_empty())
+                .map(str::to_owned)
+        };
+        Ok(Schematic {
+            name: metadata_str("Name")
+                .unwrap_or_else(|| file_name.trim_end_matches(".litematic").to_owned()),
+            author: metadata_str("Author"),
+            size: read_vec3(metadata.and_then(|metadata| metadata.get("EnclosingSize")))
+                .unwrap_or_default(),
+            region_count: regions.len(),
+            block_count,
+            materials,
+        })
+    }
+
+    fn embed(&self) -> CreateEmbed {
+        let mut description = format!(
+            "**Size:** {} × {} × {}\n**Regions:** {}\n**Blocks:** {}\n",
+            self.size[0], self.size[1], self.size[2], self.region_count, self.block_count
+        );
+        if !self.materials.is_empty() {
+            description += "\n**Materials**\n";
+        }
+        for (item, count) in self.materials.iter().take(MATERIALS_IN_EMBED) {
+            description += &format!("{}: {}\n", item, format_amount(item, *count));
+        }
+        if self.materials.len() > MATERIALS_IN_EMBED {
+            description += &format!(
+                "…and {} more in the attached list",
+                self.materials.len() - MATERIALS_IN_EMBED
+            );
+        }
+
+        let embed = CreateEmbed::new()
+            .title(&self.name)
+            .description(description);
+        match &self.author {
+            Some(author) => embed.footer(CreateEmbedFooter::new(format!("By {author}"))),
+            None => embed,
+        }
+    }
+
+    fn material_list_csv(&self) -> CreateAttachment {
+        let mut csv = "Item,Count,Stack size,Stacks,Shulker boxes\n".to_owned();
+        for (item, count) in &self.materials {
+            let stack_size = stack_size(item);
+            csv += &format!(
+                "{},{},{},{:.2},{:.2}\n",
+                item,
+                count,
+                stack_size,
+                *count as f64 / stack_size as f64,
+                *count as f64 / (stack_size * SHULKER_BOX_SLOTS) as f64
+            );
+        }
+        let file_name: String = self
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        CreateAttachment::bytes(csv, format!("{file_name}_materials.csv"))
+    }
+}
+
+fn read_vec3(tag: Option<&Tag>) -> Option<[i64; 3]> {
+    let tag = tag?;
+    Some([
+        tag.get("x")?.as_i64()?,
+        tag.get("y")?.as_i64()?,
+        tag.get("z")?.as_i64()?,
+    ])
+}
+
+/// Counts how often each palette entry occurs in Litematica's block state array, which packs
+/// entries of `max(2, ceil(log2(palette size)))` bits tightly, so that entries may span two longs.
+fn count_palette_entries(
+    block_states: &[i64],
+    palette_size: usize,
+    volume: u64,
+) -> Option<Vec<u64>> {
+    let bits = (usize::BITS - palette_size.saturating_sub(1).leading_zeros()).max(2) as u64;
+    if volume.checked_mul(bits)?.div_ceil(64) > block_states.len() as u64 {
+        return None;
+    }
+    let mask = (1u64 << bits) - 1;
+    let mut counts = vec![0; palette_size];
+    for index in 0..volume {
+        let start_bit = index * bits;
+        let start_long = (start_bit / 64) as usize;
+        let end_long = ((start_bit + bits - 1) / 64) as usize;
+        let offset = start_bit % 64;
+        let mut entry = block_states[start_long] as u64 >> offset;
+        if end_long != start_long {
+            entry |= (block_states[end_long] as u64) << (64 - offset);
+        }
+        *counts.get_mut((entry & mask) as usize)? += 1;
+    }
+    Some(counts)
+}
+
+/// Returns the items needed to place one block of the given block state.
+fn block_items(block_state: &Tag) -> Vec<(String, u64)> {
+    let Some(name) = block_state.get("Name").and_then(Tag::as_str) else {
+        return Vec::new();
+    };
+    let name = name.trim_start_matches("minecraft:");
+    let property = |key: &str| {
+        block_state
+            .get("Properties")
+            .and_then(|properties| properties.get(key))
+            .and_then(Tag::as_str)
+    };
+
+    // Multi block structures are counted once
+    if IGNORED_BLOCKS.contains(&name)
+        || property("half") == Some("upper")
+        || property("part") == Some("head")
+    {
+        return Vec::new();
+    }
+    if name == "water" || name == "lava" {
+        // Only source blocks need a bucket
+        return match property("level") {
+            Some("0") => vec![(format!("{name}_bucket"), 1)],
+            _ => Vec::new(),
+        };
+    }
+    if let Some(plant) = name.strip_prefix("potted_") {
+        let plant = match plant {
+            "azalea_bush" | "flowering_azalea_bush" => plant.trim_end_matches("_bush"),
+            _ => plant,
+        };
+        return vec![("flower_pot".to_owned(), 1), (plant.to_owned(), 1)];
+    }
+
+    let count = if name.ends_with("_slab") && property("type") == Some("double") {
+        2
+    } else {
+        COUNT_PROPERTIES
+            .iter()
+            .find_map(|key| property(key)?.parse::<u64>().ok())
+            .map_or(1, |count| count.clamp(1, MAX_BLOCK_ITEM_COUNT))
+    };
+    let item = match BLOCK_ITEMS.iter().find(|(block, _)| *block == name) {
+        Some((_, item)) => (*item).to_owned(),
+        // Wall torches, signs, banners, heads and coral fans are placed with the floor item
+        None => name.replace("wall_", ""),
+    };
+    vec![(item, count)]
+}
+
+fn stack_size(item: &str) -> u64 {
+    if item.ends_with("_bucket") || item.ends_with("_bed") || item == "cake" {
+        1
+    } else if item.ends_with("_sign") || item.ends_with("_banner") || item == "armor_stand" {
+        16
+    } else {
+        64
+    }
+}
+
+/// Formats an item count like `1234 (19 stacks + 18)` or `5000 (2 SB + 24 stacks + 8)`.
+fn format_amount(item: &str, count: u64) -> String {
+    let stack_size = stack_size(item);
+    let shulker_box_size = stack_size * SHULKER_BOX_SLOTS;
+    if count < stack_size {
+        return count.to_string();
+    }
+    let (shulker_boxes, stacks, items) = (
+        count / shulker_box_size,
+        count % shulker_box_size / stack_size,
+        count % stack_size,
+    );
+    let mut parts = Vec::new();
+    if shulker_boxes > 0 {
+        parts.push(format!("{shulker_boxes} SB"));
+    }
+    if stacks == 1 {
+        parts.push("1 stack".to_owned());
+    } else if stacks > 1 {
+        parts.push(format!("{stacks} stacks"));
+    }
+    if items > 0 {
+        parts.push(items.to_string());
+    }
+    format!("{} ({})", count, parts.join(" + "))
+}
@@ -0,0 +1,370 @@
+use crate::config;
+use crate::pterodactyl::resources::{
+    self, format_bytes, ResourceLimits, ResourceMetric, ResourceSample,
+};
+use crate::pterodactyl::{maintenance, parse_duration};
+use chrono::{DateTime, Utc};
+use serenity::builder::{
+    CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+
+const DEFAULT_RANGE: &str = "6h";
+
+const CHART_WIDTH: u32 = 800;
+const PANEL_HEIGHT: u32 = 140;
+const PADDING: u32 = 10;
+const BACKGROUND: Rgb = [255, 255, 255];
+const GRID: Rgb = [225, 225, 225];
+const THRESHOLD: Rgb = [220, 40, 40];
+const CPU_COLOR: Rgb = [40, 100, 220];
+const MEMORY_COLOR: Rgb = [30, 160, 70];
+const DISK_COLOR: Rgb = [230, 130, 20];
+const NETWORK_RX_COLOR: Rgb = [130, 50, 190];
+const NETWORK_TX_COLOR: Rgb = [220, 60, 150];
+
+type Rgb = [u8; 3];
+/// Values of a metric over time
+type Series = Vec<(DateTime<Utc>, f64)>;
+
+pub(super) fn create_command() -> CreateCommand {
+    CreateCommand::new("stats")
+        .description("Shows the resource usage history of a server")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "server", "The server name")
+                .required(true),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "range",
+            "How far back to show, e.g. 30m, 6h or 2d (default 6h)",
+        ))
+}
+
+pub(super) async fn run(ctx: &Context, command: &CommandInteraction) -> crate::Result<()> {
+    let option = |name: &str| {
+        command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_str())
+    };
+    let server_name = option("server").unwrap_or_default();
+    let range_str = option("range").unwrap_or(DEFAULT_RANGE);
+
+    let config = config::get();
+    let now = Utc::now();
+    let response = match (
+        config
+            .pterodactyl_servers
+            .iter()
+            .find(|server| server.name == server_name),
+        parse_duration(range_str).and_then(|range| now.checked_sub_signed(range)),
+    ) {
+        (None, _) => error_response(format!("Unknown server {server_name}")),
+        (_, None) => error_response(format!("Invalid range {range_str}")),
+        (Some(server), Some(since)) => match resources::history(&server.name, since) {
+            Some((samples, limits)) if samples.len() >= 2 => {
+                let max_gap = i64::try_from(config.resource_monitoring.poll_interval_seconds)
+                    .ok()
+                    .and_then(|interval| interval.checked_mul(3))
+                    .and_then(chrono::Duration::try_seconds)
+                    .unwrap_or(chrono::Duration::MAX);
+                let chart = render_chart(&samples, &limits, since, now, max_gap)?;
+                let mut content = summary(&server.display_name, range_str, &samples, &limits);
+                if maintenance::is_in_maintenance(&server.name).await {
+                    content.insert_str(0, "*This server is in maintenance*\n");
+                }
+                CreateInteractionResponseMessage::new()
+                    .content(content)
+                    .add_file(CreateAttachment::bytes(chart, "stats.png"))
+            }
+            _ => error_response(format!(
+                "Not enough resource usage data for {} yet",
+                server.display_name
+            )),
+        },
+    };
+
+    command
+        .create_response(&ctx.http, CreateInteractionResponse::Message(response))
+        .await?;
+    Ok(())
+}
+
+fn error_response(message: String) -> CreateInteractionResponseMessage {
+    CreateInteractionResponseMessage::new()
+        .content(message)
+        .ephemeral(true)
+}
+
+fn summary(
+    display_name: &str,
+    range: &str,
+    samples: &[ResourceSample],
+    limits: &ResourceLimits,
+) -> String {
+    let latest = samples[samples.len() - 1];
+    let mut summary = format!("**{display_name}** resource usage over the last {range}\n");
+    for (metric, color) in [
+        (ResourceMetric::Cpu, "blue"),
+        (ResourceMetric::Memory, "green"),
+        (ResourceMetric::Disk, "orange"),
+    ] {
+        let peak = samples
+            .iter()
+            .map(|sample| metric.value(sample))
+            .fold(0.0, f64::max);
+        let limit = match metric.limit(limits) {
+            Some(limit) => metric.format(limit),
+            None => "unlimited".to_owned(),
+        };
+        summary += &format!(
+            "{} ({}): now {}, peak {} (limit {})\n",
+            metric.name(),
+            color,
+            metric.format(metric.value(&latest)),
+            metric.format(peak),
+            limit,
+        );
+    }
+    let (rx_rates, tx_rates) = network_rates(samples);
+    let latest_rate = |rates: &[(DateTime<Utc>, f64)]| {
+        rates.last().map_or("unknown".to_owned(), |&(_, rate)| {
+            format!("{}/s", format_bytes(rate))
+        })
+    };
+    summary += &format!(
+        "Network (purple in, pink out): now {} in, {} out",
+        latest_rate(&rx_rates),
+        latest_rate(&tx_rates),
+    );
+    summary
+}
+
+/// Converts the cumulative network counters into rates in bytes per second. The counters reset
+/// when the server restarts, so intervals where they go down are skipped.
+fn network_rates(samples: &[ResourceSample]) -> (Series, Series) {
+    let mut rx_rates = Vec::new();
+    let mut tx_rates = Vec::new();
+    for pair in samples.windows(2) {
+        let seconds = (pair[1].time - pair[0].time).num_milliseconds() as f64 / 1000.0;
+        if seconds <= 0.0 {
+            continue;
+        }
+        if let Some(rx) = pair[1]
+            .network_rx_bytes
+            .checked_sub(pair[0].network_rx_bytes)
+        {
+            rx_rates.push((pair[1].time, rx as f64 / seconds));
+        }
+        if let Some(tx) = pair[1]
+            .network_tx_bytes
+            .checked_sub(pair[0].network_tx_bytes)
+        {
+            tx_rates.push((pair[1].time, tx as f64 / seconds));
+        }
+    }
+    (rx_rates, tx_rates)
+}
+
+/// Renders the samples as a PNG with one panel per metric. CPU, memory and disk are scaled to the
+/// server's limits where known, with the configured alert thresholds drawn as horizontal lines.
+fn render_chart(
+    samples: &[ResourceSample],
+    limits: &ResourceLimits,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    max_gap: chrono::Duration,
+) -> crate::Result<Vec<u8>> {
+    let monitoring = &config::get().resource_monitoring;
+    let height = PADDING + 4 * (PANEL_HEIGHT + PADDING);
+    let mut canvas = Canvas::new(CHART_WIDTH, height);
+
+    let panels = [
+        (ResourceMetric::Cpu, CPU_COLOR, monitoring.cpu_alert_percent),
+        (
+            ResourceMetric::Memory,
+            MEMORY_COLOR,
+            monitoring.memory_alert_percent,
+        ),
+        (
+            ResourceMetric::Disk,
+            DISK_COLOR,
+            monitoring.disk_alert_percent,
+        ),
+    ];
+    for (index, (metric, color, alert_percent)) in panels.into_iter().enumerate() {
+        let series: Vec<_> = samples
+            .iter()
+            .map(|sample| (sample.time, metric.value(sample)))
+            .collect();
+        let limit = metric.limit(limits);
+        let scale = limit.unwrap_or_else(|| series_max(&series) * 1.1);
+        let panel = Panel::new(index as u32, since, until, scale);
+        panel.draw_grid(&mut canvas);
+        if let (Some(limit), Some(alert_percent)) = (limit, alert_percent) {
+            panel.draw_threshold(&mut canvas, limit * alert_percent / 100.0);
+        }
+        panel.draw_series(&mut canvas, &series, max_gap, color);
+    }
+
+    let (rx_rates, tx_rates) = network_rates(samples);
+    let scale = series_max(&rx_rates).max(series_max(&tx_rates)) * 1.1;
+    let panel = Panel::new(3, since, until, scale);
+    panel.draw_grid(&mut canvas);
+    panel.draw_series(&mut canvas, &rx_rates, max_gap, NETWORK_RX_COLOR);
+    panel.draw_series(&mut canvas, &tx_rates, max_gap, NETWORK_TX_COLOR);
+
+    canvas.encode_png()
+}
+
+fn series_max(series: &[(DateTime<Utc>, f64)]) -> f64 {
+    series
+        .iter()
+        .map(|&(_, value)| value)
+        .fold(0.0, f64::max)
+        .max(1.0)
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Canvas {
+        Canvas {
+            width,
+            height,
+            pixels: BACKGROUND.repeat((width * height) as usize),
+        }
+    }
+
+    fn set_pixel(&mut self, x: i64, y: i64, color: Rgb) {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return;
+        }
+        let index = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[index..index + 3].copy_from_slice(&color);
+    }
+
+    /// Draws a two pixel thick line using Bresenham's algorithm.
+    fn draw_line(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), color: Rgb) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let step_x = if x0 < x1 { 1 } else { -1 };
+        let step_y = if y0 < y1 { 1 } else { -1 };
+        let (mut x, mut y) = (x0, y0);
+        let mut error = dx + dy;
+        loop {
+            self.set_pixel(x, y, color);
+            self.set_pixel(x, y + 1, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled_error = 2 * error;
+            if doubled_error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if doubled_error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    fn encode_png(&self) -> crate::Result<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(png)
+    }
+}
+
+/// One horizontal chart panel, mapping times and values to pixel coordinates.
+struct Panel {
+    top: i64,
+    since: DateTime<Utc>,
+    until: DateTime<Utc>,
+    scale: f64,
+}
+
+impl Panel {
+    fn new(index: u32, since: DateTime<Utc>, until: DateTime<Utc>, scale: f64) -> Panel {
+        Panel {
+            top: (PADDING + index * (PANEL_HEIGHT + PADDING)) as i64,
+            since,
+            until,
+            scale,
+        }
+    }
+
+    fn left(&self) -> i64 {
+        PADDING as i64
+    }
+
+    fn right(&self) -> i64 {
+        (CHART_WIDTH - PADDING) as i64 - 1
+    }
+
+    fn bottom(&self) -> i64 {
+        self.top + PANEL_HEIGHT as i64 - 1
+    }
+
+    fn point(&self, time: DateTime<Utc>, value: f64) -> (i64, i64) {
+        let range = (self.until - self.since).num_milliseconds().max(1) as f64;
+        let x_fraction = (time - self.since).num_milliseconds() as f64 / range;
+        let y_fraction = (value / self.scale).clamp(0.0, 1.0);
+        let x = self.left() + (x_fraction * (self.right() - self.left()) as f64).round() as i64;
+        let y = self.bottom() - (y_fraction * (self.bottom() - self.top) as f64).round() as i64;
+        (x, y)
+    }
+
+    fn draw_grid(&self, canvas: &mut Canvas) {
+        for quarter in 0..=4 {
+            let y = self.top + (self.bottom() - self.top) * quarter / 4;
+            for x in self.left()..=self.right() {
+                canvas.set_pixel(x, y, GRID);
+            }
+        }
+        for x in [self.left(), self.right()] {
+            for y in self.top..=self.bottom() {
+                canvas.set_pixel(x, y, GRID);
+            }
+        }
+    }
+
+    fn draw_threshold(&self, canvas: &mut Canvas, value: f64) {
+        let (_, y) = self.point(self.since, value);
+        for x in (self.left()..=self.right()).filter(|x| x % 8 < 4) {
+            canvas.set_pixel(x, y, THRESHOLD);
+        }
+    }
+
+    /// Draws a line through the points of a series, leaving gaps where samples are missing.
+    fn draw_series(
+        &self,
+        canvas: &mut Canvas,
+        series: &[(DateTime<Utc>, f64)],
+        max_gap: chrono::Duration,
+        color: Rgb,
+    ) {
+        for pair in series.windows(2) {
+            let ((time0, value0), (time1, value1)) = (pair[0], pair[1]);
+            if time1 - time0 > max_gap {
+                continue;
+            }
+            canvas.draw_line(self.point(time0, value0), self.point(time1, value1), color);
+        }
+    }
+}
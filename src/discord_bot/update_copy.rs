@@ -1,5 +1,5 @@
 use crate::config;
-use crate::pterodactyl::{smp_commands, PterodactylServerCategory};
+use crate::pterodactyl::{smp_commands, PterodactylPanels, PterodactylServerCategory};
 use log::warn;
 use pterodactyl_api::client::backups::Backup;
 use pterodactyl_api::client::websocket::{PteroWebSocketHandle, PteroWebSocketListener};
@@ -19,7 +19,7 @@ fn copy_update_mutex() -> &'static Mutex<()> {
 pub(crate) async fn run(
     ctx: &Context,
     command: &CommandInteraction,
-    pterodactyl: &pterodactyl_api::client::Client,
+    pterodactyl: &PterodactylPanels,
 ) -> crate::Result<()> {
     let _guard = match copy_update_mutex().try_lock() {
         Ok(guard) => guard,
@@ -38,21 +38,21 @@ pub(crate) async fn run(
     let config = config::get();
 
     let Some(smp_server) = config
-        .pterodactyl_servers(PterodactylServerCategory::Smp)
+        .pterodactyl_servers(PterodactylServerCategory::SMP)
         .next()
     else {
         warn!("No SMP server found in the config");
         return Ok(());
     };
-    let smp_server = pterodactyl.get_server(&smp_server.id);
+    let smp_server = pterodactyl.get_server(smp_server)?;
     let Some(copy_server) = config
-        .pterodactyl_servers(PterodactylServerCategory::Copy)
+        .pterodactyl_servers(PterodactylServerCategory::COPY)
         .next()
     else {
         warn!("No Copy server found in the config");
         return Ok(());
     };
-    let copy_server = pterodactyl.get_server(&copy_server.id);
+    let copy_server = pterodactyl.get_server(copy_server)?;
 
     command
         .edit_response(
@@ -0,0 +1,278 @@
+use crate::discord_bot::server_or_default_smp;
+use crate::nbt::{self, Tag};
+use crate::pterodactyl::{lookup_player, PterodactylServer};
+use crate::{config, ProtobotData};
+use serenity::builder::{
+    CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponse,
+    CreateInteractionResponseMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+
+const SCOREBOARD_FILE: &str = "world/data/scoreboard.dat";
+const PLAYERDATA_DIRECTORY: &str = "world/playerdata";
+/// Standings longer than this are sent as an attachment
+const MAX_MESSAGE_LENGTH: usize = 2000;
+
+pub(super) fn create_commands() -> [CreateCommand; 2] {
+    let server_option = || {
+        CreateCommandOption::new(
+            CommandOptionType::String,
+            "server",
+            "The server name (default SMP)",
+        )
+    };
+    [
+        CreateCommand::new("scoreboard")
+            .description("Shows the standings of a scoreboard objective")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "objective",
+                    "The objective name",
+                )
+                .required(true),
+            )
+            .add_option(server_option()),
+        CreateCommand::new("player")
+            .description("Shows where a player last was")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "player",
+                    "The Minecraft username",
+                )
+                .required(true),
+            )
+            .add_option(server_option()),
+    ]
+}
+
+pub(super) async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    data: &ProtobotData,
+) -> crate::Result<()> {
+    let option = |name: &str| {
+        command
+            .data
+            .options
+            .iter()
+            .find(|option| option.name == name)
+            .and_then(|option| option.value.as_str())
+    };
+    let config = config::get();
+    let Some(server) = server_or_default_smp(&config, option("server")) else {
+        command
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(format!(
+                            "Unknown server {}",
+                            option("server").unwrap_or_default()
+                        ))
+                        .ephemeral(true),
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    command
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+        )
+        .await?;
+
+    let response = if command.data.name == "scoreboard" {
+        match standings(data, server, option("objective").unwrap_or_default()).await {
+            Ok(response) => response,
+            Err(err) => EditInteractionResponse::new()
+                .content(format!("Failed to read the scoreboard: {err}")),
+        }
+    } else {
+        let message =
+            match player_location(data, server, option("player").unwrap_or_default()).await {
+                Ok(message) => message,
+                Err(err) => format!("Failed to read player data: {err}"),
+            };
+        EditInteractionResponse::new().content(message)
+    };
+    command.edit_response(&ctx.http, response).await?;
+    Ok(())
+}
+
+async fn read_nbt_file(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    file: String,
+) -> crate::Result<Tag> {
+    let contents = data
+        .pterodactyl
+        .get_server(server)?
+        .file_contents(file)
+        .await?;
+    let (_, root) = tokio::task::spawn_blocking(move || nbt::read_file(&contents)).await??;
+    Ok(root)
+}
+
+async fn standings(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    objective: &str,
+) -> crate::Result<EditInteractionResponse> {
+    let root = read_nbt_file(data, server, SCOREBOARD_FILE.to_owned()).await?;
+    let scoreboard = root.get("data");
+    let objectives = scoreboard
+        .and_then(|scoreboard| scoreboard.get("Objectives"))
+        .and_then(Tag::as_list)
+        .unwrap_or_default();
+    let Some(objective_tag) = objectives
+        .iter()
+        .find(|tag| tag.get("Name").and_then(Tag::as_str) == Some(objective))
+    else {
+        let mut names: Vec<_> = objectives
+            .iter()
+            .filter_map(|tag| tag.get("Name").and_then(Tag::as_str))
+            .collect();
+        names.sort_unstable();
+        return Ok(EditInteractionResponse::new().content(format!(
+            "Unknown objective `{}`. Objectives on {}: {}",
+            objective,
+            server.display_name,
+            names.join(", ")
+        )));
+    };
+    let display_name = objective_tag
+        .get("DisplayName")
+        .map(plain_text)
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| objective.to_owned());
+
+    // Names starting with # are hidden from the sidebar in game, so they are hidden here too
+    let mut scores: Vec<_> = scoreboard
+        .and_then(|scoreboard| scoreboard.get("PlayerScores"))
+        .and_then(Tag::as_list)
+        .unwrap_or_default()
+        .iter()
+        .filter(|score| score.get("Objective").and_then(Tag::as_str) == Some(objective))
+        .filter_map(|score| {
+            let name = score.get("Name").and_then(Tag::as_str)?;
+            let value = score.get("Score").and_then(Tag::as_i64)?;
+            (!name.starts_with('#')).then_some((name, value))
+        })
+        .collect();
+    scores.sort_by(|(name_a, value_a), (name_b, value_b)| {
+        value_b.cmp(value_a).then_with(|| name_a.cmp(name_b))
+    });
+
+    let mut message = format!("**{} on {}**", display_name, server.display_name);
+    if scores.is_empty() {
+        message += "\nNobody has a score yet";
+    }
+    for (rank, (name, value)) in scores.iter().enumerate() {
+        message += &format!("\n{}. {}: {}", rank + 1, name, value);
+    }
+    if message.len() <= MAX_MESSAGE_LENGTH {
+        return Ok(EditInteractionResponse::new().content(message));
+    }
+    Ok(EditInteractionResponse::new()
+        .content(format!(
+            "**{} on {}**: {} players",
+            display_name,
+            server.display_name,
+            scores.len()
+        ))
+        .new_attachment(CreateAttachment::bytes(
+            message,
+            format!("{}_{}.txt", server.name, objective),
+        )))
+}
+
+async fn player_location(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    player: &str,
+) -> crate::Result<String> {
+    let (name, uuid) = lookup_player(player).await?;
+    let root = match read_nbt_file(
+        data,
+        server,
+        format!("{}/{}.dat", PLAYERDATA_DIRECTORY, uuid.hyphenated()),
+    )
+    .await
+    {
+        Ok(root) => root,
+        Err(crate::Error::Pterodactyl(err))
+            if matches!(*err, pterodactyl_api::Error::ResourceNotFound) =>
+        {
+            return Ok(format!("{name} has never joined {}", server.display_name));
+        }
+        Err(err) => return Err(err),
+    };
+
+    let pos: Vec<_> = root
+        .get("Pos")
+        .and_then(Tag::as_list)
+        .unwrap_or_default()
+        .iter()
+        .filter_map(Tag::as_f64)
+        .collect();
+    let [x, y, z] = pos[..] else {
+        return Ok(format!("{name} has no position on {}", server.display_name));
+    };
+    // Dimensions are stored by name since 1.16, and by number before that
+    let dimension = match root.get("Dimension") {
+        Some(Tag::String(dimension)) => {
+            dimension.trim_start_matches("minecraft:").replace('_', " ")
+        }
+        Some(dimension) => match dimension.as_i64() {
+            Some(-1) => "the nether".to_owned(),
+            Some(1) => "the end".to_owned(),
+            _ => "overworld".to_owned(),
+        },
+        None => "overworld".to_owned(),
+    };
+    Ok(format!(
+        "{} was last at {:.0} {:.0} {:.0} in {} on {}",
+        name,
+        x.floor(),
+        y.floor(),
+        z.floor(),
+        dimension,
+        server.display_name
+    ))
+}
+
+/// Extracts the plain text of a text component, which is stored as JSON in a string before 1.21.5
+/// and as NBT since.
+fn plain_text(tag: &Tag) -> String {
+    fn json_text(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Array(parts) => parts.iter().map(json_text).collect(),
+            serde_json::Value::Object(object) => {
+                let text = object.get("text").map(json_text).unwrap_or_default();
+                let extra = object.get("extra").map(json_text).unwrap_or_default();
+                text + &extra
+            }
+            serde_json::Value::Null => String::new(),
+            other => other.to_string(),
+        }
+    }
+
+    match tag {
+        Tag::String(text) => serde_json::from_str(text)
+            .map(|value| json_text(&value))
+            .unwrap_or_else(|_| text.clone()),
+        Tag::List(parts) => parts.iter().map(plain_text).collect(),
+        Tag::Compound(_) => {
+            let text = tag.get("text").map(plain_text).unwrap_or_default();
+            let extra = tag.get("extra").map(plain_text).unwrap_or_default();
+            text + &extra
+        }
+        _ => String::new(),
+    }
+}
@@ -1,6 +1,8 @@
 mod application;
+mod command_sender;
 mod config;
 mod discord_bot;
+mod nbt;
 mod pterodactyl;
 mod stdin;
 mod webserver;
@@ -12,7 +14,7 @@ use flexi_logger::{
 use git_version::git_version;
 use hyper::http;
 use log::{error, info, Level, Record};
-use pterodactyl::smp_commands;
+use pterodactyl::{smp_commands, PterodactylPanels};
 use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, OnceLock};
 use std::{env, io, thread};
@@ -40,6 +42,10 @@ enum Error {
     Uuid(#[from] uuid::Error),
     #[error("Pterodactyl Error: {0}")]
     Pterodactyl(#[from] Box<pterodactyl_api::Error>),
+    #[error("PNG Error: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("NBT Error: {0}")]
+    Nbt(#[from] nbt::NbtError),
     #[error("Other Error: {0}")]
     Other(String),
 }
@@ -64,7 +70,7 @@ boxed_from!(serenity::Error, pterodactyl_api::Error);
 #[derive(Clone)]
 pub struct ProtobotData {
     pub discord_handle: discord_bot::Handle,
-    pub pterodactyl: Arc<pterodactyl_api::client::Client>,
+    pub pterodactyl: Arc<PterodactylPanels>,
 }
 
 static IS_SHUTDOWN: AtomicBool = AtomicBool::new(false);
@@ -137,13 +143,7 @@ fn main() {
         .build()
         .expect("Failed to build runtime");
 
-    let pterodactyl = Arc::new(
-        pterodactyl_api::client::ClientBuilder::new(
-            &config::get().pterodactyl_domain,
-            &config::get().pterodactyl_api_key,
-        )
-        .build(),
-    );
+    let pterodactyl = Arc::new(PterodactylPanels::from_config(&config::get()));
 
     let discord_bot = match runtime.block_on(discord_bot::create_client(pterodactyl.clone())) {
         Ok(bot) => bot,
@@ -191,6 +191,18 @@ fn main() {
         });
     }
 
+    runtime.spawn(pterodactyl::whitelist::run_expiry_task(
+        protobot_data.clone(),
+    ));
+    runtime.spawn(pterodactyl::perms_sync::run_periodic(protobot_data.clone()));
+    runtime.spawn(pterodactyl::resources::run_monitor(protobot_data.clone()));
+    runtime.spawn(pterodactyl::playtime::run_session_heartbeat());
+    runtime.spawn(discord_bot::announce::run_scheduler(protobot_data.clone()));
+    {
+        let protobot_data = protobot_data.clone();
+        runtime.spawn(async move { pterodactyl::discovery::lint_panel(&protobot_data).await });
+    }
+
     let runtime = Arc::new(runtime);
 
     {
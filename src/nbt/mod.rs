@@ -0,0 +1,257 @@
+//! A reader for Minecraft's NBT format, as used by `.dat` files such as `scoreboard.dat` and
+//! player data.
+
+#[cfg(test)]
+mod tests;
+
+use flate2::read::GzDecoder;
+use std::collections::HashMap;
+use std::io::Read;
+
+/// Compounds and lists nested deeper than this are rejected, to avoid overflowing the stack on
+/// malicious input. Minecraft uses the same limit.
+const MAX_DEPTH: usize = 512;
+/// Compressed files larger than this once decompressed are rejected.
+const MAX_DECOMPRESSED_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum NbtError {
+    #[error("unexpected end of data")]
+    UnexpectedEof,
+    #[error("unknown tag type {0}")]
+    UnknownTagType(u8),
+    #[error("root tag must be a compound, but was type {0}")]
+    RootNotCompound(u8),
+    #[error("negative length {0}")]
+    NegativeLength(i32),
+    #[error("nesting is deeper than {MAX_DEPTH}")]
+    TooDeep,
+    #[error("data is larger than {} MiB when decompressed", MAX_DECOMPRESSED_SIZE / 1024 / 1024)]
+    TooLarge,
+    #[error("decompression failed: {0}")]
+    Decompression(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    Compound(HashMap<String, Tag>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(compound) => compound.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns any integer tag as an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(value) => Some(value.into()),
+            Tag::Short(value) => Some(value.into()),
+            Tag::Int(value) => Some(value.into()),
+            Tag::Long(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns any numeric tag as an `f64`.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Tag::Float(value) => Some(value.into()),
+            Tag::Double(value) => Some(value),
+            _ => self.as_i64().map(|value| value as f64),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(list) => Some(list),
+            _ => None,
+        }
+    }
+
+    pub fn as_compound(&self) -> Option<&HashMap<String, Tag>> {
+        match self {
+            Tag::Compound(compound) => Some(compound),
+            _ => None,
+        }
+    }
+
+    pub fn as_long_array(&self) -> Option<&[i64]> {
+        match self {
+            Tag::LongArray(array) => Some(array),
+            _ => None,
+        }
+    }
+}
+
+/// Reads an NBT file, which may be gzip compressed. Returns the name of the root tag and the root
+/// compound.
+pub fn read_file(data: &[u8]) -> Result<(String, Tag), NbtError> {
+    if data.starts_with(&[0x1f, 0x8b]) {
+        let mut decompressed = Vec::new();
+        GzDecoder::new(data)
+            .take(MAX_DECOMPRESSED_SIZE + 1)
+            .read_to_end(&mut decompressed)?;
+        if decompressed.len() as u64 > MAX_DECOMPRESSED_SIZE {
+            return Err(NbtError::TooLarge);
+        }
+        read_uncompressed(&decompressed)
+    } else {
+        read_uncompressed(data)
+    }
+}
+
+pub fn read_uncompressed(data: &[u8]) -> Result<(String, Tag), NbtError> {
+    let mut reader = Reader { data };
+    let tag_type = reader.read_u8()?;
+    if tag_type != TAG_COMPOUND {
+        return Err(NbtError::RootNotCompound(tag_type));
+    }
+    let name = reader.read_string()?;
+    let root = reader.read_payload(tag_type, 0)?;
+    Ok((name, root))
+}
+
+const TAG_END: u8 = 0;
+const TAG_BYTE: u8 = 1;
+const TAG_SHORT: u8 = 2;
+const TAG_INT: u8 = 3;
+const TAG_LONG: u8 = 4;
+const TAG_FLOAT: u8 = 5;
+const TAG_DOUBLE: u8 = 6;
+const TAG_BYTE_ARRAY: u8 = 7;
+const TAG_STRING: u8 = 8;
+const TAG_LIST: u8 = 9;
+const TAG_COMPOUND: u8 = 10;
+const TAG_INT_ARRAY: u8 = 11;
+const TAG_LONG_ARRAY: u8 = 12;
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], NbtError> {
+        if self.data.len() < len {
+            return Err(NbtError::UnexpectedEof);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], NbtError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_u8(&mut self) -> Result<u8, NbtError> {
+        Ok(self.take_array::<1>()?[0])
+    }
+
+    fn read_i16(&mut self) -> Result<i16, NbtError> {
+        Ok(i16::from_be_bytes(self.take_array()?))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, NbtError> {
+        Ok(i32::from_be_bytes(self.take_array()?))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, NbtError> {
+        Ok(i64::from_be_bytes(self.take_array()?))
+    }
+
+    fn read_length(&mut self) -> Result<usize, NbtError> {
+        let length = self.read_i32()?;
+        usize::try_from(length).map_err(|_| NbtError::NegativeLength(length))
+    }
+
+    /// Strings are in Java's modified UTF-8, which only differs from UTF-8 for null characters and
+    /// characters outside the BMP. Those are rare enough in practice to be read lossily.
+    fn read_string(&mut self) -> Result<String, NbtError> {
+        let length = u16::from_be_bytes(self.take_array()?);
+        Ok(String::from_utf8_lossy(self.take(length.into())?).into_owned())
+    }
+
+    fn read_array<T>(
+        &mut self,
+        element_size: usize,
+        read_element: impl Fn(&[u8]) -> T,
+    ) -> Result<Vec<T>, NbtError> {
+        let length = self.read_length()?;
+        let bytes = self.take(
+            length
+                .checked_mul(element_size)
+                .ok_or(NbtError::UnexpectedEof)?,
+        )?;
+        Ok(bytes.chunks_exact(element_size).map(read_element).collect())
+    }
+
+    fn read_payload(&mut self, tag_type: u8, depth: usize) -> Result<Tag, NbtError> {
+        if depth > MAX_DEPTH {
+            return Err(NbtError::TooDeep);
+        }
+        Ok(match tag_type {
+            TAG_BYTE => Tag::Byte(self.read_u8()? as i8),
+            TAG_SHORT => Tag::Short(self.read_i16()?),
+            TAG_INT => Tag::Int(self.read_i32()?),
+            TAG_LONG => Tag::Long(self.read_i64()?),
+            TAG_FLOAT => Tag::Float(f32::from_be_bytes(self.take_array()?)),
+            TAG_DOUBLE => Tag::Double(f64::from_be_bytes(self.take_array()?)),
+            TAG_BYTE_ARRAY => Tag::ByteArray(self.read_array(1, |bytes| bytes[0] as i8)?),
+            TAG_STRING => Tag::String(self.read_string()?),
+            TAG_LIST => {
+                let element_type = self.read_u8()?;
+                let length = self.read_i32()?;
+                // Empty lists may have TAG_End as their element type, which has no payload
+                if length <= 0 {
+                    return Ok(Tag::List(Vec::new()));
+                }
+                let mut list = Vec::new();
+                for _ in 0..length {
+                    list.push(self.read_payload(element_type, depth + 1)?);
+                }
+                Tag::List(list)
+            }
+            TAG_COMPOUND => {
+                let mut compound = HashMap::new();
+                loop {
+                    let element_type = self.read_u8()?;
+                    if element_type == TAG_END {
+                        break;
+                    }
+                    let name = self.read_string()?;
+                    compound.insert(name, self.read_payload(element_type, depth + 1)?);
+                }
+                Tag::Compound(compound)
+            }
+            TAG_INT_ARRAY => Tag::IntArray(
+                self.read_array(4, |bytes| i32::from_be_bytes(bytes.try_into().unwrap()))?,
+            ),
+            TAG_LONG_ARRAY => Tag::LongArray(
+                self.read_array(8, |bytes| i64::from_be_bytes(bytes.try_into().unwrap()))?,
+            ),
+            _ => return Err(NbtError::UnknownTagType(tag_type)),
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use super::{read_file, read_uncompressed, NbtError, Tag};
+
+const SCOREBOARD: &[u8] = include_bytes!("fixtures/scoreboard.dat");
+const PLAYERDATA: &[u8] = include_bytes!("fixtures/playerdata.dat");
+const PLAYERDATA_LEGACY: &[u8] = include_bytes!("fixtures/playerdata_legacy.dat");
+
+#[test]
+fn reads_scoreboard() {
+    let (name, root) = read_file(SCOREBOARD).unwrap();
+    assert_eq!(name, "");
+    assert_eq!(root.get("DataVersion"), Some(&Tag::Int(3953)));
+
+    let data = root.get("data").unwrap();
+    let objectives = data.get("Objectives").and_then(Tag::as_list).unwrap();
+    assert_eq!(objectives.len(), 2);
+    assert_eq!(
+        objectives[1].get("DisplayName").and_then(Tag::as_str),
+        Some(r#"{"text":"Deaths","color":"red"}"#)
+    );
+
+    let scores: Vec<_> = data
+        .get("PlayerScores")
+        .and_then(Tag::as_list)
+        .unwrap()
+        .iter()
+        .map(|score| {
+            (
+                score.get("Name").and_then(Tag::as_str).unwrap(),
+                score.get("Objective").and_then(Tag::as_str).unwrap(),
+                score.get("Score").and_then(Tag::as_i64).unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        scores,
+        [
+            ("Alice", "digs", 1500),
+            ("Bob", "digs", 3200),
+            ("Alice", "deaths", 4),
+            ("Carol", "digs", -2),
+        ]
+    );
+
+    // An empty list of TAG_End
+    assert_eq!(data.get("Teams"), Some(&Tag::List(Vec::new())));
+}
+
+#[test]
+fn reads_playerdata() {
+    let (_, root) = read_file(PLAYERDATA).unwrap();
+    let pos: Vec<_> = root
+        .get("Pos")
+        .and_then(Tag::as_list)
+        .unwrap()
+        .iter()
+        .map(|coordinate| coordinate.as_f64().unwrap())
+        .collect();
+    assert_eq!(pos, [128.5, 64.0, -32.25]);
+    assert_eq!(
+        root.get("Rotation"),
+        Some(&Tag::List(vec![Tag::Float(90.0), Tag::Float(0.0)]))
+    );
+    assert_eq!(
+        root.get("Dimension").and_then(Tag::as_str),
+        Some("minecraft:the_nether")
+    );
+    assert_eq!(root.get("UUID"), Some(&Tag::IntArray(vec![1, 2, 3, 4])));
+    assert_eq!(root.get("SleepTimer"), Some(&Tag::Short(0)));
+    assert_eq!(root.get("seenCredits"), Some(&Tag::Long(1)));
+    assert_eq!(root.get("Bytes"), Some(&Tag::ByteArray(vec![1, -1, 3])));
+    assert_eq!(root.get("Longs"), Some(&Tag::LongArray(vec![-1, 1 << 40])));
+
+    let inventory = root.get("Inventory").and_then(Tag::as_list).unwrap();
+    assert_eq!(inventory[0].get("Slot"), Some(&Tag::Byte(0)));
+    assert_eq!(
+        inventory[0].get("id").and_then(Tag::as_str),
+        Some("minecraft:diamond_pickaxe")
+    );
+}
+
+#[test]
+fn reads_uncompressed_legacy_playerdata() {
+    let (_, root) = read_file(PLAYERDATA_LEGACY).unwrap();
+    assert_eq!(root.get("Dimension").and_then(Tag::as_i64), Some(-1));
+    assert_eq!(root.get("Dimension").and_then(Tag::as_str), None);
+    assert_eq!(
+        root.get("Pos").and_then(Tag::as_list).map(<[Tag]>::len),
+        Some(3)
+    );
+}
+
+#[test]
+fn rejects_truncated_data() {
+    for len in 0..PLAYERDATA_LEGACY.len() {
+        assert!(matches!(
+            read_uncompressed(&PLAYERDATA_LEGACY[..len]),
+            Err(NbtError::UnexpectedEof)
+        ));
+    }
+}
+
+#[test]
+fn rejects_invalid_data() {
+    assert!(matches!(
+        read_uncompressed(&[8, 0, 0, 0, 0]),
+        Err(NbtError::RootNotCompound(8))
+    ));
+    assert!(matches!(
+        read_uncompressed(&[10, 0, 0, 13, 0, 0, 0]),
+        Err(NbtError::UnknownTagType(13))
+    ));
+    assert!(matches!(
+        read_uncompressed(&[10, 0, 0, 11, 0, 0, 0xff, 0xff, 0xff, 0xff, 0]),
+        Err(NbtError::NegativeLength(-1))
+    ));
+    assert!(matches!(
+        read_file(&[0x1f, 0x8b, 0, 0]),
+        Err(NbtError::Decompression(_))
+    ));
+}
+
+#[test]
+fn rejects_deep_nesting() {
+    // Lists of lists, nested far deeper than allowed
+    let mut data = vec![10, 0, 0, 9, 0, 0];
+    for _ in 0..1000 {
+        data.extend([9, 0, 0, 0, 1]);
+    }
+    assert!(matches!(read_uncompressed(&data), Err(NbtError::TooDeep)));
+}
@@ -0,0 +1,380 @@
+use crate::command_sender::CommandSender;
+use crate::pterodactyl::{
+    lookup_player, parse_managed_category, read_category_json, run_across_categories,
+    write_list_file, PterodactylServerCategory,
+};
+use crate::{config, ProtobotData};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use serenity::builder::{CreateEmbed, CreateMessage};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+const DEFAULT_REASON: &str = "Banned by an operator.";
+
+pub(crate) async fn run(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    let Some(operation) = args.next() else {
+        print_usage(sender);
+        return Ok(());
+    };
+    match operation {
+        "add" | "add_ip" => {
+            let Some(target) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let reason = args.collect::<Vec<_>>().join(" ");
+            let reason = if reason.is_empty() {
+                DEFAULT_REASON.to_owned()
+            } else {
+                reason
+            };
+
+            let applied = AtomicBool::new(false);
+            if operation == "add" {
+                let name_and_uuid = OnceCell::new();
+                run_across_categories(sender, category, |category| {
+                    let name_and_uuid = &name_and_uuid;
+                    let reason = &reason;
+                    let applied = &applied;
+                    async move {
+                        let (player_name, player_uuid) = name_and_uuid
+                            .get_or_try_init(|| lookup_player(target))
+                            .await?;
+                        if ban_player(data, sender, player_name, *player_uuid, category, reason)
+                            .await?
+                        {
+                            applied.store(true, Ordering::Relaxed);
+                        }
+                        Ok(())
+                    }
+                })
+                .await?;
+            } else {
+                let Ok(ip) = target.parse::<IpAddr>() else {
+                    sender.error(format!("Invalid IP address {target}"));
+                    return Ok(());
+                };
+                run_across_categories(sender, category, |category| {
+                    let reason = &reason;
+                    let applied = &applied;
+                    async move {
+                        if ban_ip(data, sender, ip, category, reason).await? {
+                            applied.store(true, Ordering::Relaxed);
+                        }
+                        Ok(())
+                    }
+                })
+                .await?;
+            }
+
+            if applied.into_inner() {
+                post_audit_log(data, sender, "Banned", target, category, Some(&reason)).await;
+            }
+        }
+        "remove" | "remove_ip" => {
+            let Some(target) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+
+            let applied = AtomicBool::new(false);
+            if operation == "remove" {
+                run_across_categories(sender, category, |category| {
+                    let applied = &applied;
+                    async move {
+                        if unban_player(data, sender, target, category).await? {
+                            applied.store(true, Ordering::Relaxed);
+                        }
+                        Ok(())
+                    }
+                })
+                .await?;
+            } else {
+                let Ok(ip) = target.parse::<IpAddr>() else {
+                    sender.error(format!("Invalid IP address {target}"));
+                    return Ok(());
+                };
+                run_across_categories(sender, category, |category| {
+                    let applied = &applied;
+                    async move {
+                        if unban_ip(data, sender, ip, category).await? {
+                            applied.store(true, Ordering::Relaxed);
+                        }
+                        Ok(())
+                    }
+                })
+                .await?;
+            }
+
+            if applied.into_inner() {
+                post_audit_log(data, sender, "Unbanned", target, category, None).await;
+            }
+        }
+        "list" => {
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = parse_managed_category(sender, category) else {
+                return Ok(());
+            };
+            bans_list(data, sender, category).await?;
+        }
+        _ => {
+            print_usage(sender);
+        }
+    }
+    Ok(())
+}
+
+/// Bans a player on every server in a category. Vanilla servers can't reload their ban lists, so
+/// the `ban` command is also run, which applies the ban immediately and kicks the player if online.
+async fn ban_player(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    player_name: &str,
+    player_uuid: Uuid,
+    category: PterodactylServerCategory,
+    reason: &str,
+) -> crate::Result<bool> {
+    let Some(mut bans) =
+        read_category_json::<Vec<PlayerBan>>(data, sender, category, "banned-players.json").await?
+    else {
+        return Ok(false);
+    };
+
+    if bans.iter().any(|ban| ban.uuid == player_uuid) {
+        sender.error(format!("{player_name} was already banned"));
+        return Ok(false);
+    }
+
+    bans.push(PlayerBan {
+        uuid: player_uuid,
+        name: player_name.to_owned(),
+        details: BanDetails::new(sender, reason),
+    });
+
+    write_list_file(
+        data,
+        category,
+        "banned-players.json",
+        serde_json::to_string_pretty(&bans)?,
+        format!("ban {player_name} {reason}"),
+        |server| sender.info(format!("Banned {player_name} on {server}")),
+    )
+    .await?;
+    Ok(true)
+}
+
+async fn unban_player(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    player_name: &str,
+    category: PterodactylServerCategory,
+) -> crate::Result<bool> {
+    let Some(mut bans) =
+        read_category_json::<Vec<PlayerBan>>(data, sender, category, "banned-players.json").await?
+    else {
+        return Ok(false);
+    };
+
+    let num_bans = bans.len();
+    bans.retain(|ban| !ban.name.eq_ignore_ascii_case(player_name));
+    if bans.len() == num_bans {
+        sender.error(format!("{player_name} was not banned"));
+        return Ok(false);
+    }
+
+    write_list_file(
+        data,
+        category,
+        "banned-players.json",
+        serde_json::to_string_pretty(&bans)?,
+        format!("pardon {player_name}"),
+        |server| sender.info(format!("Unbanned {player_name} on {server}")),
+    )
+    .await?;
+    Ok(true)
+}
+
+async fn ban_ip(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    ip: IpAddr,
+    category: PterodactylServerCategory,
+    reason: &str,
+) -> crate::Result<bool> {
+    let Some(mut bans) =
+        read_category_json::<Vec<IpBan>>(data, sender, category, "banned-ips.json").await?
+    else {
+        return Ok(false);
+    };
+
+    if bans.iter().any(|ban| ban.ip == ip) {
+        sender.error(format!("{ip} was already banned"));
+        return Ok(false);
+    }
+
+    bans.push(IpBan {
+        ip,
+        details: BanDetails::new(sender, reason),
+    });
+
+    write_list_file(
+        data,
+        category,
+        "banned-ips.json",
+        serde_json::to_string_pretty(&bans)?,
+        format!("ban-ip {ip} {reason}"),
+        |server| sender.info(format!("Banned IP {ip} on {server}")),
+    )
+    .await?;
+    Ok(true)
+}
+
+async fn unban_ip(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    ip: IpAddr,
+    category: PterodactylServerCategory,
+) -> crate::Result<bool> {
+    let Some(mut bans) =
+        read_category_json::<Vec<IpBan>>(data, sender, category, "banned-ips.json").await?
+    else {
+        return Ok(false);
+    };
+
+    let num_bans = bans.len();
+    bans.retain(|ban| ban.ip != ip);
+    if bans.len() == num_bans {
+        sender.error(format!("{ip} was not banned"));
+        return Ok(false);
+    }
+
+    write_list_file(
+        data,
+        category,
+        "banned-ips.json",
+        serde_json::to_string_pretty(&bans)?,
+        format!("pardon-ip {ip}"),
+        |server| sender.info(format!("Unbanned IP {ip} on {server}")),
+    )
+    .await?;
+    Ok(true)
+}
+
+async fn bans_list(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+) -> crate::Result<()> {
+    let Some(player_bans) =
+        read_category_json::<Vec<PlayerBan>>(data, sender, category, "banned-players.json").await?
+    else {
+        return Ok(());
+    };
+    let Some(ip_bans) =
+        read_category_json::<Vec<IpBan>>(data, sender, category, "banned-ips.json").await?
+    else {
+        return Ok(());
+    };
+
+    if player_bans.is_empty() && ip_bans.is_empty() {
+        sender.info("There are no bans");
+        return Ok(());
+    }
+    for ban in player_bans {
+        sender.info(format!(
+            "{}: banned by {} on {}: {}",
+            ban.name, ban.details.source, ban.details.created, ban.details.reason
+        ));
+    }
+    for ban in ip_bans {
+        sender.info(format!(
+            "{}: banned by {} on {}: {}",
+            ban.ip, ban.details.source, ban.details.created, ban.details.reason
+        ));
+    }
+    Ok(())
+}
+
+async fn post_audit_log(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    action: &str,
+    target: &str,
+    category: &str,
+    reason: Option<&str>,
+) {
+    let Some(ban_log) = config::get().special_channels.ban_log else {
+        return;
+    };
+    let mut embed = CreateEmbed::new()
+        .title(format!("{action} {target}"))
+        .field("Category", category, true)
+        .field("Issuer", sender.name(), true);
+    if let Some(reason) = reason {
+        embed = embed.field("Reason", reason, false);
+    }
+    if let Err(err) = ban_log
+        .send_message(&data.discord_handle, CreateMessage::new().embed(embed))
+        .await
+    {
+        warn!("Failed to post to the ban log: {}", err);
+    }
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(ban add <player> <category|all> [reason]) | (ban remove <player> <category|all>) | (ban add_ip <ip> <category|all> [reason]) | (ban remove_ip <ip> <category|all>) | (ban list <category>)");
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct PlayerBan {
+    uuid: Uuid,
+    name: String,
+    #[serde(flatten)]
+    details: BanDetails,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct IpBan {
+    ip: IpAddr,
+    #[serde(flatten)]
+    details: BanDetails,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct BanDetails {
+    created: String,
+    source: String,
+    expires: String,
+    reason: String,
+}
+
+impl BanDetails {
+    fn new(sender: &CommandSender, reason: &str) -> Self {
+        Self {
+            created: chrono::Utc::now()
+                .format("%Y-%m-%d %H:%M:%S %z")
+                .to_string(),
+            source: sender.name().to_owned(),
+            expires: "forever".to_owned(),
+            reason: reason.to_owned(),
+        }
+    }
+}
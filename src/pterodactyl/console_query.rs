@@ -0,0 +1,66 @@
+use crate::pterodactyl::{send_command_safe, PterodactylServer};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// How long to wait for the console output of a command
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Picks the response to a query out of a console line
+type Matcher = Box<dyn Fn(&str) -> Option<String> + Send>;
+
+struct PendingQuery {
+    matcher: Matcher,
+    response: oneshot::Sender<String>,
+}
+
+/// The queries waiting for output by server name
+type PendingQueries = HashMap<String, Vec<PendingQuery>>;
+
+fn pending_queries() -> &'static Mutex<PendingQueries> {
+    static PENDING_QUERIES: OnceLock<Mutex<PendingQueries>> = OnceLock::new();
+    PENDING_QUERIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Runs a command and waits for its output, since the panel only returns output through the
+/// console. Returns the output picked by `matcher` from the first console line it matches, or
+/// `None` if no line matched in time.
+pub(crate) async fn query(
+    server: &PterodactylServer,
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+    command: impl Into<String>,
+    matcher: impl Fn(&str) -> Option<String> + Send + 'static,
+) -> crate::Result<Option<String>> {
+    let (response_sender, response) = oneshot::channel();
+    pending_queries()
+        .lock()
+        .unwrap()
+        .entry(server.name.clone())
+        .or_default()
+        .push(PendingQuery {
+            matcher: Box::new(matcher),
+            response: response_sender,
+        });
+    send_command_safe(ptero_server, command).await?;
+    Ok(tokio::time::timeout(QUERY_TIMEOUT, response)
+        .await
+        .ok()
+        .and_then(Result::ok))
+}
+
+/// Answers the first pending query that matches the console line.
+pub(crate) fn handle_console_line(server: &PterodactylServer, line: &str) {
+    let mut pending_queries = pending_queries().lock().unwrap();
+    let Some(queries) = pending_queries.get_mut(&server.name) else {
+        return;
+    };
+    // Queries that timed out are no longer waited for
+    queries.retain(|query| !query.response.is_closed());
+    for index in 0..queries.len() {
+        if let Some(output) = (queries[index].matcher)(line) {
+            let _ = queries.remove(index).response.send(output);
+            break;
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use crate::command_sender::CommandSender;
+use crate::pterodactyl::{
+    is_chat_line, send_command_safe, tellraw, PterodactylServer, PterodactylServerCategory,
+};
+use crate::{config, ProtobotData};
+use chrono::{DateTime, Utc};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Deserializer};
+use serenity::builder::{CreateAllowedMentions, CreateMessage};
+use serenity::model::id::{ChannelId, RoleId};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+/// When each rule last triggered, by rule and server name
+type RuleTriggers = HashMap<(String, String), DateTime<Utc>>;
+
+fn last_triggers() -> &'static Mutex<RuleTriggers> {
+    static LAST_TRIGGERS: OnceLock<Mutex<RuleTriggers>> = OnceLock::new();
+    LAST_TRIGGERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(crate) async fn run(
+    _data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    match args.next() {
+        Some("list") => {
+            let config = config::get();
+            if config.console_rules.is_empty() {
+                sender.info("There are no console rules");
+            }
+            for rule in &config.console_rules {
+                sender.info(format!(
+                    "{}: /{}/ with {} actions, cooldown {}s",
+                    rule.name,
+                    rule.pattern,
+                    rule.actions.len(),
+                    rule.cooldown_seconds
+                ));
+            }
+        }
+        Some("test") => {
+            let Some(server_name) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let line = args.collect::<Vec<_>>().join(" ");
+            let config = config::get();
+            let Some(server) = config
+                .pterodactyl_servers
+                .iter()
+                .find(|server| server.name == server_name)
+            else {
+                sender.error(format!("Unknown server {server_name}"));
+                return Ok(());
+            };
+            test_line(sender, server, &line);
+        }
+        _ => print_usage(sender),
+    }
+    Ok(())
+}
+
+/// Prints which rules match a line and what their actions would do, without running them.
+fn test_line(sender: &CommandSender, server: &PterodactylServer, line: &str) {
+    if is_chat_line(line) {
+        sender.info("That line was written by a player, so no rules apply to it");
+        return;
+    }
+    let config = config::get();
+    let mut matched = false;
+    for rule in &config.console_rules {
+        if !rule.applies_to(server) {
+            continue;
+        }
+        let Some(captures) = rule.pattern.captures(line) else {
+            continue;
+        };
+        matched = true;
+        sender.info(format!("Rule {} matches", rule.name));
+        for action in &rule.actions {
+            sender.info(format!("  {}", action.describe(&captures, server)));
+        }
+    }
+    if !matched {
+        sender.info("No rules match");
+    }
+}
+
+/// Runs the actions of every rule matching a console line, unless the rule is on cooldown. Lines
+/// written by players are ignored, so that players can't trigger rules.
+pub(crate) async fn handle_console_line(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    line: &str,
+) -> crate::Result<()> {
+    if is_chat_line(line) {
+        return Ok(());
+    }
+    let config = config::get();
+    for rule in &config.console_rules {
+        if !rule.applies_to(server) {
+            continue;
+        }
+        let Some(captures) = rule.pattern.captures(line) else {
+            continue;
+        };
+        if !rule.try_trigger(server) {
+            continue;
+        }
+        for action in &rule.actions {
+            action.run(data, &captures, server).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces `{1}` or `{name}` with the corresponding capture group, and `{server}` with the display
+/// name of the server. Unknown placeholders are left as is.
+fn expand_template(template: &str, captures: &Captures, server: &PterodactylServer) -> String {
+    expand_template_with(template, captures, server, |capture| capture)
+}
+
+/// Like `expand_template`, but refuses captures that could change what a command does, returning
+/// the first such capture.
+fn expand_command_template<'h>(
+    template: &str,
+    captures: &Captures<'h>,
+    server: &PterodactylServer,
+) -> Result<String, &'h str> {
+    let mut unsafe_capture = None;
+    let command = expand_template_with(template, captures, server, |capture| {
+        if capture
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || matches!(char, '_' | '.' | ':' | '-'))
+        {
+            capture
+        } else {
+            unsafe_capture.get_or_insert(capture);
+            ""
+        }
+    });
+    match unsafe_capture {
+        Some(capture) => Err(capture),
+        None => Ok(command),
+    }
+}
+
+fn expand_template_with<'h>(
+    template: &str,
+    captures: &Captures<'h>,
+    server: &PterodactylServer,
+    mut map_capture: impl FnMut(&'h str) -> &'h str,
+) -> String {
+    let mut result = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result += &rest[..start];
+        let after_brace = &rest[start + 1..];
+        let Some(end) = after_brace.find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let key = &after_brace[..end];
+        let replacement = if key == "server" {
+            Some(server.display_name.as_str())
+        } else if let Ok(index) = key.parse::<usize>() {
+            captures
+                .get(index)
+                .map(|capture| map_capture(capture.as_str()))
+        } else {
+            captures
+                .name(key)
+                .map(|capture| map_capture(capture.as_str()))
+        };
+        match replacement {
+            Some(replacement) => result += replacement,
+            None => result += &rest[start..start + end + 2],
+        }
+        rest = &after_brace[end + 1..];
+    }
+    result += rest;
+    result
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(rules list) | (rules test <server> <line...>)");
+}
+
+/// Reacts to console lines matching a regex, which is matched against the whole line including the
+/// `[time] [thread/LEVEL]: ` prefix.
+#[derive(Debug, Deserialize)]
+pub struct ConsoleRule {
+    pub name: String,
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub pattern: Regex,
+    /// The servers the rule applies to. If both this and `categories` are empty, the rule applies
+    /// to every server.
+    #[serde(default)]
+    pub servers: Vec<String>,
+    #[serde(default)]
+    pub categories: Vec<PterodactylServerCategory>,
+    pub actions: Vec<ConsoleRuleAction>,
+    /// The minimum time between two triggers of the rule on the same server
+    #[serde(default)]
+    pub cooldown_seconds: u64,
+}
+
+impl ConsoleRule {
+    fn applies_to(&self, server: &PterodactylServer) -> bool {
+        (self.servers.is_empty() && self.categories.is_empty())
+            || self.servers.contains(&server.name)
+            || self.categories.contains(&server.category)
+    }
+
+    /// Records a trigger of the rule, returning false if the rule is still on cooldown. Cooldowns
+    /// too long to represent never end.
+    fn try_trigger(&self, server: &PterodactylServer) -> bool {
+        let now = Utc::now();
+        let cooldown = i64::try_from(self.cooldown_seconds)
+            .ok()
+            .and_then(chrono::Duration::try_seconds);
+        let key = (self.name.clone(), server.name.clone());
+        let mut last_triggers = last_triggers().lock().unwrap();
+        if last_triggers.get(&key).is_some_and(|&last_trigger| {
+            cooldown
+                .and_then(|cooldown| last_trigger.checked_add_signed(cooldown))
+                .is_none_or(|cooldown_end| cooldown_end > now)
+        }) {
+            return false;
+        }
+        last_triggers.insert(key, now);
+        true
+    }
+}
+
+fn deserialize_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    Regex::new(&pattern).map_err(serde::de::Error::custom)
+}
+
+/// Messages and commands may contain placeholders, see `expand_template`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConsoleRuleAction {
+    Post {
+        channel: ChannelId,
+        message: String,
+    },
+    PingRole {
+        channel: ChannelId,
+        role: RoleId,
+        message: String,
+    },
+    /// Captures may only contain letters, digits and `_.:-`, so that they can't change the command
+    RunCommand {
+        command: String,
+    },
+    Tellraw {
+        message: String,
+    },
+}
+
+impl ConsoleRuleAction {
+    async fn run(
+        &self,
+        data: &ProtobotData,
+        captures: &Captures<'_>,
+        server: &PterodactylServer,
+    ) -> crate::Result<()> {
+        match self {
+            ConsoleRuleAction::Post { channel, message } => {
+                channel
+                    .send_message(
+                        &data.discord_handle,
+                        CreateMessage::new()
+                            .content(expand_template(message, captures, server))
+                            .allowed_mentions(CreateAllowedMentions::new()),
+                    )
+                    .await?;
+            }
+            ConsoleRuleAction::PingRole {
+                channel,
+                role,
+                message,
+            } => {
+                channel
+                    .send_message(
+                        &data.discord_handle,
+                        CreateMessage::new()
+                            .content(format!(
+                                "<@&{}> {}",
+                                role,
+                                expand_template(message, captures, server)
+                            ))
+                            .allowed_mentions(CreateAllowedMentions::new().roles([*role])),
+                    )
+                    .await?;
+            }
+            ConsoleRuleAction::RunCommand { command } => {
+                let command =
+                    expand_command_template(command, captures, server).map_err(|capture| {
+                        crate::Error::Other(format!(
+                            "refused to run a command with {capture:?} in it"
+                        ))
+                    })?;
+                let ptero_server = data.pterodactyl.get_server(server)?;
+                send_command_safe(&ptero_server, command).await?;
+            }
+            ConsoleRuleAction::Tellraw { message } => {
+                let ptero_server = data.pterodactyl.get_server(server)?;
+                tellraw(&ptero_server, expand_template(message, captures, server)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn describe(&self, captures: &Captures, server: &PterodactylServer) -> String {
+        match self {
+            ConsoleRuleAction::Post { channel, message } => format!(
+                "Post to {}: {}",
+                channel,
+                expand_template(message, captures, server)
+            ),
+            ConsoleRuleAction::PingRole {
+                channel,
+                role,
+                message,
+            } => format!(
+                "Ping role {} in {}: {}",
+                role,
+                channel,
+                expand_template(message, captures, server)
+            ),
+            ConsoleRuleAction::RunCommand { command } => {
+                match expand_command_template(command, captures, server) {
+                    Ok(command) => format!("Run command: {command}"),
+                    Err(capture) => format!("Refuse to run command with {capture:?} in it"),
+                }
+            }
+            ConsoleRuleAction::Tellraw { message } => {
+                format!("Tellraw: {}", expand_template(message, captures, server))
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+use crate::command_sender::CommandSender;
+use crate::config;
+use crate::pterodactyl::{http_client, maintenance, PterodactylPanel, DEFAULT_PANEL_NAME};
+use crate::ProtobotData;
+use futures::future::join_all;
+use log::warn;
+use pterodactyl_api::client::ServerState;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+
+/// The most servers the panel lists per request
+const EGG_LIST_PAGE_SIZE: u32 = 100;
+
+pub(crate) async fn run(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    match args.next() {
+        Some("list") => list_servers(data, sender).await,
+        Some("generate") => {
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let ids: Vec<_> = args.collect();
+            if ids.is_empty() {
+                print_usage(sender);
+                return Ok(());
+            }
+            generate_entries(data, sender, category, &ids).await
+        }
+        _ => {
+            print_usage(sender);
+            Ok(())
+        }
+    }
+}
+
+/// Lists every server the API keys can see, and how they relate to the configured servers.
+async fn list_servers(data: &ProtobotData, sender: &CommandSender) -> crate::Result<()> {
+    let config = config::get();
+    let mut panel_server_ids = HashSet::new();
+    for (panel_name, panel) in data.pterodactyl.iter() {
+        let panel_servers = match panel.list_servers().await {
+            Ok(servers) => servers,
+            Err(err) => {
+                sender.error(format!(
+                    "Failed to list servers on panel {panel_name}: {err}"
+                ));
+                continue;
+            }
+        };
+        // Listing servers still works without eggs, so failing to get them isn't fatal
+        let egg_names = match config
+            .pterodactyl_panels()
+            .find(|(name, _)| *name == panel_name)
+        {
+            Some((_, panel_config)) => egg_names(&panel_config).await.unwrap_or_else(|err| {
+                warn!("Failed to get eggs on panel {}: {}", panel_name, err);
+                HashMap::new()
+            }),
+            None => HashMap::new(),
+        };
+        let states = join_all(panel_servers.iter().map(|server| async {
+            panel
+                .get_server(&server.identifier)
+                .get_resources()
+                .await
+                .map(|resources| resources.current_state)
+        }))
+        .await;
+
+        sender.info(format!(
+            "{} servers on panel {}:",
+            panel_servers.len(),
+            panel_name
+        ));
+        for (server, state) in panel_servers.iter().zip(states) {
+            let state = match state {
+                Ok(ServerState::Offline) => "offline",
+                Ok(ServerState::Starting) => "starting",
+                Ok(ServerState::Running) => "running",
+                Ok(ServerState::Stopping) => "stopping",
+                Err(_) => "unknown",
+            };
+            let config_status = match config.pterodactyl_servers.iter().find(|configured| {
+                configured.panel == panel_name && configured.id == server.identifier
+            }) {
+                Some(configured) => {
+                    let mut config_status = format!(
+                        "configured as {} ({:?})",
+                        configured.name, configured.category
+                    );
+                    if maintenance::is_in_maintenance(&configured.name).await {
+                        config_status += ", in maintenance";
+                    }
+                    config_status
+                }
+                None => "MISSING FROM CONFIG".to_owned(),
+            };
+            let egg = egg_names
+                .get(&server.identifier)
+                .map_or("unknown egg", String::as_str);
+            sender.info(format!(
+                "  {} \"{}\": {}, egg {} (image {}), {}",
+                server.identifier, server.name, state, egg, server.docker_image, config_status
+            ));
+            panel_server_ids.insert((panel_name, server.identifier.clone()));
+        }
+    }
+
+    for server in &config.pterodactyl_servers {
+        if !panel_server_ids.contains(&(server.panel.as_str(), server.id.clone())) {
+            sender.info(format!(
+                "Configured server {} has unknown id {} on panel {}",
+                server.name, server.id, server.panel
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the egg name of each server the panel's API key can see, by server identifier. The API
+/// client doesn't expose eggs, so they are requested from the client API directly.
+async fn egg_names(panel: &PterodactylPanel) -> crate::Result<HashMap<String, String>> {
+    #[derive(Deserialize)]
+    struct ServerList {
+        data: Vec<ServerObject>,
+        meta: ListMeta,
+    }
+    #[derive(Deserialize)]
+    struct ListMeta {
+        pagination: Pagination,
+    }
+    #[derive(Deserialize)]
+    struct Pagination {
+        total_pages: u32,
+    }
+    #[derive(Deserialize)]
+    struct ServerObject {
+        attributes: ServerAttributes,
+    }
+    #[derive(Deserialize)]
+    struct ServerAttributes {
+        identifier: String,
+        relationships: ServerRelationships,
+    }
+    #[derive(Deserialize)]
+    struct ServerRelationships {
+        egg: EggObject,
+    }
+    #[derive(Deserialize)]
+    struct EggObject {
+        attributes: EggAttributes,
+    }
+    #[derive(Deserialize)]
+    struct EggAttributes {
+        name: String,
+    }
+
+    let client = http_client()?;
+    let mut egg_names = HashMap::new();
+    let mut page = 1;
+    loop {
+        let servers: ServerList = client
+            .get(format!(
+                "{}/api/client?include=egg&per_page={EGG_LIST_PAGE_SIZE}&page={page}",
+                panel.domain.trim_end_matches('/')
+            ))
+            .header("Accept", "application/json")
+            .bearer_auth(&panel.api_key)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        egg_names.extend(servers.data.into_iter().map(|server| {
+            (
+                server.attributes.identifier,
+                server.attributes.relationships.egg.attributes.name,
+            )
+        }));
+        if page >= servers.meta.pagination.total_pages {
+            return Ok(egg_names);
+        }
+        page += 1;
+    }
+}
+
+/// Prints config entries for the given panel servers, ready to be added to `pterodactyl_servers`.
+async fn generate_entries(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: &str,
+    ids: &[&str],
+) -> crate::Result<()> {
+    let config = config::get();
+    if !config
+        .pterodactyl_categories
+        .keys()
+        .any(|known_category| known_category.name() == category)
+    {
+        sender.error(format!("Unknown category {category}"));
+        return Ok(());
+    }
+
+    let mut panel_servers = Vec::new();
+    for (panel_name, panel) in data.pterodactyl.iter() {
+        panel_servers.extend(
+            panel
+                .list_servers()
+                .await?
+                .into_iter()
+                .map(|server| (panel_name, server)),
+        );
+    }
+
+    let mut entries = Vec::new();
+    for &id in ids {
+        let Some((panel_name, server)) = panel_servers
+            .iter()
+            .find(|(_, server)| server.identifier == id)
+        else {
+            sender.error(format!("No server with id {id} on any panel"));
+            continue;
+        };
+        if config
+            .pterodactyl_servers
+            .iter()
+            .any(|server| server.panel == *panel_name && server.id == id)
+        {
+            sender.info(format!("Server {id} is already in the config"));
+        }
+        let mut entry = json!({
+            "id": server.identifier,
+            "name": config_name(&server.name),
+            "display_name": server.name,
+            "category": category,
+        });
+        if *panel_name != DEFAULT_PANEL_NAME {
+            entry["panel"] = json!(panel_name);
+        }
+        entries.push(entry);
+    }
+
+    if !entries.is_empty() {
+        sender.info(serde_json::to_string_pretty(&entries)?);
+    }
+    Ok(())
+}
+
+/// Warns about configured servers which no longer exist on their panel.
+pub(crate) async fn lint_panel(data: &ProtobotData) {
+    for (panel_name, panel) in data.pterodactyl.iter() {
+        let panel_servers = match panel.list_servers().await {
+            Ok(servers) => servers,
+            Err(err) => {
+                warn!(
+                    "Failed to list servers on panel {} for config lint: {}",
+                    panel_name, err
+                );
+                continue;
+            }
+        };
+        for server in config::get()
+            .pterodactyl_servers
+            .iter()
+            .filter(|server| server.panel == panel_name)
+        {
+            if !panel_servers
+                .iter()
+                .any(|panel_server| panel_server.identifier == server.id)
+            {
+                warn!(
+                    "Server {} has id {} which does not exist on panel {}",
+                    server.name, server.id, panel_name
+                );
+            }
+        }
+    }
+}
+
+fn config_name(display_name: &str) -> String {
+    let name: String = display_name
+        .chars()
+        .map(|char| {
+            if char.is_ascii_alphanumeric() {
+                char.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(servers list) | (servers generate <category> <id...>)");
+}
@@ -0,0 +1,355 @@
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::discord_bot::reports::{self, PlayerReport};
+use crate::pterodactyl::smp_commands::create_backup;
+use crate::pterodactyl::{
+    console_query, ops, role_whitelist, tellraw, tellraw_to, PterodactylServer,
+};
+use crate::{config, ProtobotData};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// When each command was last used, by server name, command name, and player name for commands
+/// with per player cooldowns
+type Cooldowns = HashMap<(String, &'static str, Option<String>), Instant>;
+
+fn cooldowns() -> &'static Mutex<Cooldowns> {
+    static COOLDOWNS: OnceLock<Mutex<Cooldowns>> = OnceLock::new();
+    COOLDOWNS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Declares the in-game `!` commands. Levels work like op levels: 0 lets everyone use the command,
+/// and higher levels need ops of that level or a linked Discord account with a role granting it.
+/// Cooldowns are either shared by everyone on a server, or kept for each player.
+macro_rules! declare_game_commands {
+    ($(($name:literal, $func:path, level: $level:literal, cooldown: $cooldown:literal per $scope:ident, $description:literal);)*) => {
+        const GAME_COMMANDS: &[GameCommand] = &[
+            $(
+            GameCommand {
+                name: $name,
+                level: $level,
+                cooldown: Duration::from_secs($cooldown),
+                cooldown_scope: CooldownScope::$scope,
+                description: $description,
+            },
+            )*
+        ];
+
+        async fn run_command(name: &str, context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+            match name {
+                $(
+                $name => $func(context, args).await,
+                )*
+                _ => Ok(()),
+            }
+        }
+    }
+}
+
+declare_game_commands! {
+    ("backup", backup, level: 3, cooldown: 600 per Server, "creates a backup of the server");
+    ("help", help, level: 0, cooldown: 10 per Player, "lists the commands you can use");
+    ("link", link, level: 0, cooldown: 5 per Player, "verifies your linked Discord account, e.g. !link <code>");
+    ("report", report, level: 0, cooldown: 30 per Player, "sends a report to staff, e.g. !report <message>");
+    ("s", sidebar, level: 0, cooldown: 5 per Server, "shows an objective in the sidebar, or hides it");
+    ("staff", staff, level: 0, cooldown: 30 per Player, "asks staff for help, optionally with a message");
+    ("t", tab_list, level: 0, cooldown: 5 per Server, "shows an objective in the tab list, or hides it");
+}
+
+struct GameCommand {
+    name: &'static str,
+    level: u8,
+    cooldown: Duration,
+    cooldown_scope: CooldownScope,
+    description: &'static str,
+}
+
+enum CooldownScope {
+    Server,
+    Player,
+}
+
+struct GameCommandContext<'a> {
+    data: &'a ProtobotData,
+    server: &'a PterodactylServer,
+    ptero_server: &'a pterodactyl_api::client::Server<'a>,
+    player: &'a str,
+}
+
+impl GameCommandContext<'_> {
+    /// Replies to the player who ran the command only.
+    async fn reply(&self, message: impl Into<String>) -> crate::Result<()> {
+        // Names that went through a chat plugin can't be targeted
+        if !is_valid_player_name(self.player) {
+            return Ok(());
+        }
+        tellraw_to(self.ptero_server, self.player, message).await
+    }
+
+    /// The highest of the player's op level and the levels granted by the Discord roles of the
+    /// account they linked.
+    async fn permission_level(&self) -> crate::Result<u8> {
+        let op_level = ops::op_level(self.ptero_server, self.player).await?;
+        let config = config::get();
+        if config.game_command_roles.is_empty() {
+            return Ok(op_level);
+        }
+        let user_id = GuildStorage::get(config.guild_id)
+            .await
+            .linked_accounts
+            .iter()
+            .find(|(_, account)| account.verified && account.name.eq_ignore_ascii_case(self.player))
+            .map(|(user_id, _)| *user_id);
+        let Some(user_id) = user_id else {
+            return Ok(op_level);
+        };
+        let role_level = match config
+            .guild_id
+            .member(&self.data.discord_handle, user_id)
+            .await
+        {
+            Ok(member) => member
+                .roles
+                .iter()
+                .filter_map(|role| config.game_command_roles.get(role))
+                .copied()
+                .max()
+                .unwrap_or(0),
+            Err(err) => {
+                warn!(
+                    "Failed to get roles of {} ({}): {}",
+                    self.player, user_id, err
+                );
+                0
+            }
+        };
+        Ok(op_level.max(role_level))
+    }
+}
+
+/// Runs an in-game `!` command, with the `!` removed, sent by a player in chat.
+pub(crate) async fn handle_command(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+    player: &str,
+    command: &str,
+) -> crate::Result<()> {
+    let args: Vec<_> = command.split_whitespace().collect();
+    let Some((&name, args)) = args.split_first() else {
+        return Ok(());
+    };
+    let context = GameCommandContext {
+        data,
+        server,
+        ptero_server,
+        player,
+    };
+    // Players say things starting with `!` that aren't commands, so those are ignored
+    let Some(command) = GAME_COMMANDS.iter().find(|command| command.name == name) else {
+        return Ok(());
+    };
+
+    if command.level > 0 && context.permission_level().await? < command.level {
+        return context
+            .reply(format!(
+                "You need permission level {} to use !{}",
+                command.level, command.name
+            ))
+            .await;
+    }
+
+    let remaining_cooldown = {
+        let mut cooldowns = cooldowns().lock().unwrap();
+        let player = match command.cooldown_scope {
+            CooldownScope::Server => None,
+            CooldownScope::Player => Some(player.to_lowercase()),
+        };
+        let key = (server.name.clone(), command.name, player);
+        let now = Instant::now();
+        match cooldowns.get(&key) {
+            Some(&last_used) if now < last_used + command.cooldown => {
+                Some(last_used + command.cooldown - now)
+            }
+            _ => {
+                cooldowns.insert(key, now);
+                None
+            }
+        }
+    };
+    if let Some(remaining_cooldown) = remaining_cooldown {
+        return context
+            .reply(format!(
+                "!{} can be used again in {} seconds",
+                command.name,
+                remaining_cooldown.as_secs() + 1
+            ))
+            .await;
+    }
+
+    info!(
+        "Received command {} from {} on {}",
+        command.name, player, server.name
+    );
+    run_command(command.name, &context, args).await
+}
+
+async fn help(context: &GameCommandContext<'_>, _args: &[&str]) -> crate::Result<()> {
+    let level = context.permission_level().await?;
+    let commands: Vec<_> = GAME_COMMANDS
+        .iter()
+        .filter(|command| command.level <= level)
+        .map(|command| format!("!{}: {}", command.name, command.description))
+        .collect();
+    context.reply(commands.join("\n")).await
+}
+
+async fn link(context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+    let [code] = args else {
+        return context
+            .reply("Usage: !link <code>, with the code you got from /link on Discord")
+            .await;
+    };
+    // Names that went through a chat plugin may not be the player's real name
+    if !is_valid_player_name(context.player) {
+        return Ok(());
+    }
+    if role_whitelist::verify_account(context.data, context.player, code).await? {
+        context.reply("Your Discord account is now verified").await
+    } else {
+        context
+            .reply("That code is wrong, use /link on Discord to get a new one")
+            .await
+    }
+}
+
+async fn sidebar(context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+    set_display(context, "sidebar", args.first().copied()).await
+}
+
+async fn tab_list(context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+    set_display(context, "list", args.first().copied()).await
+}
+
+async fn set_display(
+    context: &GameCommandContext<'_>,
+    slot: &str,
+    objective: Option<&str>,
+) -> crate::Result<()> {
+    match objective {
+        Some(objective) if !is_valid_objective_name(objective) => {
+            context
+                .reply(format!("Invalid objective name {objective}"))
+                .await
+        }
+        Some(objective) => {
+            context
+                .ptero_server
+                .send_command(format!(
+                    "scoreboard objectives setdisplay {slot} {objective}"
+                ))
+                .await?;
+            Ok(())
+        }
+        None => {
+            context
+                .ptero_server
+                .send_command(format!("scoreboard objectives setdisplay {slot}"))
+                .await?;
+            Ok(())
+        }
+    }
+}
+
+async fn backup(context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+    let name = (!args.is_empty()).then(|| args.join(" "));
+    create_backup(context.ptero_server, name).await?;
+    tellraw(
+        context.ptero_server,
+        "Backup being created. Wait a minute to be sure the backup has finished",
+    )
+    .await
+}
+
+async fn report(context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+    if args.is_empty() {
+        return context.reply("Usage: !report <message>").await;
+    }
+    send_report(context, "Report", args).await
+}
+
+async fn staff(context: &GameCommandContext<'_>, args: &[&str]) -> crate::Result<()> {
+    send_report(context, "Staff request", args).await
+}
+
+async fn send_report(
+    context: &GameCommandContext<'_>,
+    title: &str,
+    args: &[&str],
+) -> crate::Result<()> {
+    let location = if is_valid_player_name(context.player) {
+        player_location(context).await?
+    } else {
+        None
+    };
+    let report = PlayerReport {
+        title,
+        player: context.player,
+        server: &context.server.display_name,
+        message: (!args.is_empty()).then(|| args.join(" ")),
+        location,
+    };
+    if reports::post_report(context.data, report).await? {
+        context
+            .reply("Your message was sent to staff, who will get back to you soon")
+            .await
+    } else {
+        context
+            .reply("Reports are not set up, please contact staff on Discord")
+            .await
+    }
+}
+
+/// Asks the server where the player is, as `x y z in dimension`.
+async fn player_location(context: &GameCommandContext<'_>) -> crate::Result<Option<String>> {
+    let entity_data = |path: &str| {
+        let prefix = format!("{} has the following entity data: ", context.player);
+        console_query::query(
+            context.server,
+            context.ptero_server,
+            format!("data get entity {} {}", context.player, path),
+            // Anchored to the log prefix, so that players can't fake the output in chat
+            move |line| Some(line.split_once("]: ")?.1.strip_prefix(&prefix)?.to_owned()),
+        )
+    };
+    // Both queries match the same lines, so they must not run at the same time
+    let Some(pos) = entity_data("Pos").await? else {
+        return Ok(None);
+    };
+    let coordinates: Vec<_> = pos
+        .trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .filter_map(|coordinate| coordinate.trim().trim_end_matches('d').parse::<f64>().ok())
+        .map(|coordinate| coordinate.floor().to_string())
+        .collect();
+    if coordinates.len() != 3 {
+        return Ok(None);
+    }
+    let dimension = entity_data("Dimension").await?;
+    let dimension = dimension
+        .as_deref()
+        .map_or("unknown dimension", |dimension| {
+            dimension.trim_matches('"').trim_start_matches("minecraft:")
+        });
+    Ok(Some(format!("{} in {}", coordinates.join(" "), dimension)))
+}
+
+fn is_valid_player_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_valid_objective_name(name: &str) -> bool {
+    name.chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '+'))
+}
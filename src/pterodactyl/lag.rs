@@ -0,0 +1,260 @@
+use crate::config::{self, LagMonitoring};
+use crate::pterodactyl::{is_chat, online_players, PterodactylServer};
+use crate::ProtobotData;
+use chrono::{DateTime, Utc};
+use log::warn;
+use nom::bytes::complete::{tag, take_until1};
+use nom::character::complete::{char, digit1};
+use nom::combinator::rest;
+use nom::{Finish, Parser};
+use serenity::builder::CreateMessage;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+
+fn lag_histories() -> &'static Mutex<HashMap<String, LagHistory>> {
+    static LAG_HISTORIES: OnceLock<Mutex<HashMap<String, LagHistory>>> = OnceLock::new();
+    LAG_HISTORIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Records a lag warning if the console line is one, and reports to staff if the server has lagged
+/// too much in the configured window.
+pub(crate) async fn handle_console_line(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    line: &str,
+) -> crate::Result<()> {
+    let Some((kind, lag_ms)) = parse_lag_line(line) else {
+        return Ok(());
+    };
+    let players = online_players::online_players(&server.name);
+    let event = LagEvent {
+        time: Utc::now(),
+        kind,
+        lag_ms,
+        players: players.len(),
+    };
+
+    let config = config::get();
+    let report = lag_histories()
+        .lock()
+        .unwrap()
+        .entry(server.name.clone())
+        .or_default()
+        .record(event, &config.lag_monitoring);
+    let Some(report) = report else {
+        return Ok(());
+    };
+
+    let mut message = format!(
+        "**{}** is lagging. In the last {} minutes:\n{}",
+        server.display_name, config.lag_monitoring.window_minutes, report
+    );
+    if players.is_empty() {
+        message += "\nNo players are online";
+    } else {
+        message += &format!(
+            "\nPlayers online now ({}): {}",
+            players.len(),
+            players.join(", ")
+        );
+    }
+    warn!("{}", message);
+    if let Some(staff_channel) = config.special_channels.staff {
+        staff_channel
+            .send_message(&data.discord_handle, CreateMessage::new().content(message))
+            .await?;
+    }
+    Ok(())
+}
+
+/// Parses lag related console lines, returning the kind of lag and how long the server lagged for
+/// in milliseconds.
+fn parse_lag_line(line: &str) -> Option<(LagKind, u64)> {
+    let parse_result: Result<(&str, (_, &str)), nom::error::Error<&str>> = (
+        (
+            char('['),
+            digit1,
+            char(':'),
+            digit1,
+            char(':'),
+            digit1,
+            tag("] ["),
+            take_until1("]: "),
+            tag("]: "),
+        ),
+        rest,
+    )
+        .parse(line)
+        .finish();
+    let (_, ((_, _, _, _, _, _, _, thread, _), message)) = parse_result.ok()?;
+
+    // Can't keep up! Is the server overloaded? Running 2345ms or 46 ticks behind
+    if let Some(running) = message
+        .strip_prefix("Can't keep up!")
+        .and_then(|message| message.split_once("Running "))
+        .map(|(_, running)| running)
+    {
+        let millis = running.split_once("ms")?.0.parse().ok()?;
+        return Some((LagKind::CantKeepUp, millis));
+    }
+
+    // A single server tick took 60.00 seconds (should be max 0.05)
+    if let Some(took) = message.strip_prefix("A single server tick took ") {
+        return Some((LagKind::Watchdog, parse_lag_duration(took)?));
+    }
+
+    // The server has not responded for 10 seconds! Creating thread dump
+    if let Some(responded) = message.strip_prefix("The server has not responded for ") {
+        return Some((LagKind::Watchdog, parse_lag_duration(responded)?));
+    }
+
+    // Mods word long tick warnings differently, so any line mentioning a tick taking too long
+    // counts, as long as it isn't something a player said
+    if is_chat(thread, message) {
+        return None;
+    }
+    let lowercase = message.to_ascii_lowercase();
+    let (_, took) = lowercase.split_once("tick took ")?;
+    Some((LagKind::LongTick, parse_lag_duration(took)?))
+}
+
+/// Parses durations such as "1234ms", "1.5 seconds" or "60.00s" at the start of a string.
+fn parse_lag_duration(duration: &str) -> Option<u64> {
+    let number_len = duration
+        .find(|char: char| !char.is_ascii_digit() && char != '.')
+        .unwrap_or(duration.len());
+    let (number, unit) = duration.split_at(number_len);
+    let number: f64 = number.parse().ok()?;
+    let unit = unit.trim_start();
+    if unit.starts_with("ms") {
+        Some(number as u64)
+    } else if unit.starts_with('s') {
+        Some((number * 1000.0) as u64)
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+struct LagHistory {
+    events: VecDeque<LagEvent>,
+    last_report: Option<DateTime<Utc>>,
+}
+
+impl LagHistory {
+    /// Records a lag event, returning a report if the events in the window cross a threshold.
+    fn record(&mut self, event: LagEvent, monitoring: &LagMonitoring) -> Option<String> {
+        // Out of range windows are rejected when the config is loaded
+        let window_start = i64::try_from(monitoring.window_minutes)
+            .ok()
+            .and_then(chrono::Duration::try_minutes)
+            .and_then(|window| event.time.checked_sub_signed(window))?;
+        self.events.push_back(event);
+        while self
+            .events
+            .front()
+            .is_some_and(|old_event| old_event.time < window_start)
+        {
+            self.events.pop_front();
+        }
+
+        // Only report once per window, so that a struggling server doesn't spam staff
+        if self
+            .last_report
+            .is_some_and(|last_report| last_report > window_start)
+        {
+            return None;
+        }
+
+        let total_lag_ms: u64 = self.events.iter().map(|event| event.lag_ms).sum();
+        let has_watchdog = self
+            .events
+            .iter()
+            .any(|event| event.kind == LagKind::Watchdog);
+        if !has_watchdog
+            && self.events.len() < monitoring.events_threshold
+            && total_lag_ms < monitoring.lag_ms_threshold
+        {
+            return None;
+        }
+
+        self.last_report = Some(event.time);
+        Some(self.report())
+    }
+
+    fn report(&self) -> String {
+        let mut report = String::new();
+        for kind in [LagKind::CantKeepUp, LagKind::LongTick, LagKind::Watchdog] {
+            let events: Vec<_> = self
+                .events
+                .iter()
+                .filter(|event| event.kind == kind)
+                .collect();
+            if events.is_empty() {
+                continue;
+            }
+            let total_ms: u64 = events.iter().map(|event| event.lag_ms).sum();
+            let worst_ms = events.iter().map(|event| event.lag_ms).max().unwrap_or(0);
+            report += &format!(
+                "- {} {}: {:.1}s in total, worst {:.1}s\n",
+                events.len(),
+                kind.description(),
+                total_ms as f64 / 1000.0,
+                worst_ms as f64 / 1000.0,
+            );
+        }
+
+        let min_players = self.events.iter().map(|event| event.players).min();
+        let max_players = self.events.iter().map(|event| event.players).max();
+        if let (Some(min_players), Some(max_players)) = (min_players, max_players) {
+            let lag_per_player = |players: usize| {
+                self.events
+                    .iter()
+                    .filter(|event| event.players == players)
+                    .map(|event| event.lag_ms)
+                    .sum::<u64>() as f64
+                    / 1000.0
+            };
+            if min_players == max_players {
+                report += &format!("Players online during the lag: {min_players}");
+            } else {
+                report += &format!(
+                    "Players online during the lag: {} to {} ({:.1}s of lag with {} online, {:.1}s with {} online)",
+                    min_players,
+                    max_players,
+                    lag_per_player(min_players),
+                    min_players,
+                    lag_per_player(max_players),
+                    max_players,
+                );
+            }
+        }
+        report
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LagEvent {
+    time: DateTime<Utc>,
+    kind: LagKind,
+    lag_ms: u64,
+    /// The number of players online when the lag happened
+    players: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LagKind {
+    CantKeepUp,
+    LongTick,
+    Watchdog,
+}
+
+impl LagKind {
+    fn description(self) -> &'static str {
+        match self {
+            LagKind::CantKeepUp => "\"Can't keep up\" warnings",
+            LagKind::LongTick => "long ticks",
+            LagKind::Watchdog => "watchdog warnings",
+        }
+    }
+}
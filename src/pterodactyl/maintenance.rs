@@ -0,0 +1,357 @@
+use crate::command_sender::CommandSender;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::smp_commands::broadcast_system_message;
+use crate::pterodactyl::whitelist::Player;
+use crate::pterodactyl::{
+    console_query, online_players, send_command_safe, tellraw, PterodactylServer,
+    LIST_FILE_WRITE_DELAY,
+};
+use crate::{config, ProtobotData};
+use chrono::Utc;
+use futures::future::join_all;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+const DEFAULT_MESSAGE: &str = "The server is down for maintenance, please check back later";
+
+pub(crate) async fn run(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    let operation = args.next();
+    if operation == Some("list") {
+        list(sender).await;
+        return Ok(());
+    }
+    let (Some(operation @ ("on" | "off")), Some(target)) = (operation, args.next()) else {
+        print_usage(sender);
+        return Ok(());
+    };
+    let Some(servers) = parse_target(sender, target) else {
+        return Ok(());
+    };
+    let message = args.collect::<Vec<_>>().join(" ");
+    let message = if message.is_empty() {
+        DEFAULT_MESSAGE.to_owned()
+    } else {
+        message
+    };
+
+    let results = join_all(servers.iter().map(|server| async {
+        if operation == "on" {
+            enable(data, sender, server, &message).await
+        } else {
+            disable(data, sender, server).await
+        }
+    }))
+    .await;
+    for (server, result) in servers.iter().zip(results) {
+        if let Err(err) = result {
+            sender.error(format!(
+                "Failed to turn maintenance {} on {}: {}",
+                operation, server.name, err
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a server name, or a category name meaning every Minecraft server in that category.
+fn parse_target(sender: &CommandSender, target: &str) -> Option<Vec<PterodactylServer>> {
+    let config = config::get();
+    if let Some(server) = config
+        .pterodactyl_servers
+        .iter()
+        .find(|server| server.name == target)
+    {
+        if !config.category_info(server.category).minecraft {
+            sender.error(format!("{} is not a Minecraft server", server.name));
+            return None;
+        }
+        return Some(vec![server.clone()]);
+    }
+    let Some((&category, info)) = config
+        .pterodactyl_categories
+        .iter()
+        .find(|(category, _)| category.name() == target)
+    else {
+        sender.error(format!("Unknown server or category {target}"));
+        return None;
+    };
+    if !info.minecraft {
+        sender.error(format!("{target} is not a Minecraft server category"));
+        return None;
+    }
+    Some(config.pterodactyl_servers(category).cloned().collect())
+}
+
+/// Locks a server down to staff: the whitelist is saved and replaced with one containing only
+/// staff, and everyone else is kicked.
+async fn enable(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    server: &PterodactylServer,
+    message: &str,
+) -> crate::Result<()> {
+    let config = config::get();
+    if is_in_maintenance(&server.name).await {
+        sender.info(format!("{} is already in maintenance", server.name));
+        return Ok(());
+    }
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let saved_whitelist = ptero_server.file_contents_text("whitelist.json").await?;
+    let whitelist_enforced = ptero_server
+        .file_contents_text("server.properties")
+        .await?
+        .lines()
+        .any(|line| line.trim() == "white-list=true");
+    let staff = staff_players(data, &ptero_server).await?;
+
+    // The saved whitelist is stored before anything changes, so that it can always be restored
+    {
+        let mut storage = GuildStorage::get_mut(config.guild_id).await;
+        storage.maintenance.insert(
+            server.name.clone(),
+            MaintenanceState {
+                saved_whitelist,
+                whitelist_enforced,
+                message: message.to_owned(),
+                since: Utc::now().timestamp(),
+            },
+        );
+        storage.save().await;
+    }
+
+    ptero_server
+        .write_file("whitelist.json", serde_json::to_string_pretty(&staff)?)
+        .await?;
+    tokio::time::sleep(LIST_FILE_WRITE_DELAY).await;
+    send_command_safe(&ptero_server, "whitelist reload").await?;
+    if !whitelist_enforced {
+        send_command_safe(&ptero_server, "whitelist on").await?;
+    }
+
+    let staff_names: BTreeSet<_> = staff
+        .iter()
+        .map(|player| player.name.to_lowercase())
+        .collect();
+    let mut kicked = 0;
+    for player in online_players(server, &ptero_server).await? {
+        if !staff_names.contains(&player.to_lowercase()) {
+            send_command_safe(&ptero_server, format!("kick {player} {message}")).await?;
+            kicked += 1;
+        }
+    }
+
+    let notice = format!("{} is now in maintenance: {}", server.display_name, message);
+    tellraw(&ptero_server, &notice).await?;
+    if let Err(err) = broadcast_system_message(data, server, notice).await {
+        warn!(
+            "Failed to post maintenance notice for {}: {}",
+            server.name, err
+        );
+    }
+    sender.info(format!(
+        "{} is now in maintenance, whitelisted {} staff and kicked {} players",
+        server.name,
+        staff.len(),
+        kicked
+    ));
+    Ok(())
+}
+
+/// Ends maintenance on a server, restoring the whitelist it had before exactly.
+async fn disable(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    server: &PterodactylServer,
+) -> crate::Result<()> {
+    let config = config::get();
+    let Some(state) = GuildStorage::get(config.guild_id)
+        .await
+        .maintenance
+        .get(&server.name)
+        .cloned()
+    else {
+        sender.info(format!("{} is not in maintenance", server.name));
+        return Ok(());
+    };
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let mut restored_whitelist = state.saved_whitelist;
+    loop {
+        ptero_server
+            .write_file("whitelist.json", restored_whitelist.clone())
+            .await?;
+        // Whitelist changes made while restoring are saved to the maintenance state, so restore
+        // again until it stops changing
+        let saved_whitelist = GuildStorage::get(config.guild_id)
+            .await
+            .maintenance
+            .get(&server.name)
+            .map(|state| state.saved_whitelist.clone());
+        match saved_whitelist {
+            Some(saved_whitelist) if saved_whitelist != restored_whitelist => {
+                restored_whitelist = saved_whitelist;
+            }
+            _ => break,
+        }
+    }
+    tokio::time::sleep(LIST_FILE_WRITE_DELAY).await;
+    send_command_safe(&ptero_server, "whitelist reload").await?;
+    if !state.whitelist_enforced {
+        send_command_safe(&ptero_server, "whitelist off").await?;
+    }
+
+    // Only forgotten once the whitelist is back, so that a failed restore can be retried
+    {
+        let mut storage = GuildStorage::get_mut(config.guild_id).await;
+        storage.maintenance.remove(&server.name);
+        storage.save().await;
+    }
+
+    let notice = format!("{} is no longer in maintenance", server.display_name);
+    tellraw(&ptero_server, &notice).await?;
+    if let Err(err) = broadcast_system_message(data, server, notice).await {
+        warn!(
+            "Failed to post maintenance notice for {}: {}",
+            server.name, err
+        );
+    }
+    sender.info(format!(
+        "{} is no longer in maintenance, restored its whitelist",
+        server.name
+    ));
+    Ok(())
+}
+
+/// The players allowed on a server during maintenance: its ops, and the verified linked accounts of
+/// members with panel access or the staff role.
+async fn staff_players(
+    data: &ProtobotData,
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+) -> crate::Result<Vec<Player>> {
+    let config = config::get();
+    // Ops have more fields than whitelist entries, which are ignored
+    let mut staff: BTreeSet<Player> =
+        serde_json::from_str(&ptero_server.file_contents_text("ops.json").await?)?;
+    let linked_accounts: Vec<_> = GuildStorage::get(config.guild_id)
+        .await
+        .linked_accounts
+        .iter()
+        .filter(|(_, account)| account.verified)
+        .map(|(&user_id, account)| (user_id, account.clone()))
+        .collect();
+    for (user_id, account) in linked_accounts {
+        let member = match config.guild_id.member(&data.discord_handle, user_id).await {
+            Ok(member) => member,
+            Err(err) => {
+                warn!(
+                    "Failed to get roles of {} ({}): {}",
+                    account.name, user_id, err
+                );
+                continue;
+            }
+        };
+        if config.special_roles.is_staff(&member.roles) {
+            staff.insert(Player {
+                name: account.name,
+                uuid: account.uuid,
+            });
+        }
+    }
+    Ok(staff.into_iter().collect())
+}
+
+/// Asks the server who is online, as players who joined while the bot was down aren't tracked.
+/// Falls back to the tracked players if the server doesn't answer.
+async fn online_players(
+    server: &PterodactylServer,
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+) -> crate::Result<Vec<String>> {
+    // There are 2 of a max of 20 players online: Steve, Alex
+    let players = console_query::query(server, ptero_server, "list", |line| {
+        let (_, message) = line.split_once("]: ")?;
+        let (_, players) = message
+            .strip_prefix("There are ")?
+            .split_once(" players online:")?;
+        Some(players.trim().to_owned())
+    })
+    .await?;
+    Ok(match players {
+        Some(players) => players
+            .split(", ")
+            .filter(|player| !player.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        None => online_players::online_players(&server.name),
+    })
+}
+
+/// Reads the whitelist of a server. The live whitelist of a server in maintenance only has staff on
+/// it, so the whitelist it gets back when maintenance ends is read instead.
+pub(crate) async fn read_whitelist(
+    server: &PterodactylServer,
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+) -> crate::Result<String> {
+    let saved_whitelist = GuildStorage::get(config::get().guild_id)
+        .await
+        .maintenance
+        .get(&server.name)
+        .map(|state| state.saved_whitelist.clone());
+    match saved_whitelist {
+        Some(saved_whitelist) => Ok(saved_whitelist),
+        None => Ok(ptero_server.file_contents_text("whitelist.json").await?),
+    }
+}
+
+/// Changes the whitelist a server gets back when maintenance ends, so that whitelist changes made
+/// during maintenance aren't lost. Returns whether the server is in maintenance.
+pub(crate) async fn set_saved_whitelist(server_name: &str, whitelist_json: &str) -> bool {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let Some(state) = storage.maintenance.get_mut(server_name) else {
+        storage.discard();
+        return false;
+    };
+    whitelist_json.clone_into(&mut state.saved_whitelist);
+    storage.save().await;
+    true
+}
+
+pub(crate) async fn is_in_maintenance(server_name: &str) -> bool {
+    GuildStorage::get(config::get().guild_id)
+        .await
+        .maintenance
+        .contains_key(server_name)
+}
+
+async fn list(sender: &CommandSender) {
+    let storage = GuildStorage::get(config::get().guild_id).await;
+    if storage.maintenance.is_empty() {
+        sender.info("No servers are in maintenance");
+        return;
+    }
+    for (server_name, state) in &storage.maintenance {
+        let hours = (Utc::now().timestamp() - state.since) / 3600;
+        sender.info(format!(
+            "{} has been in maintenance for {} hours: {}",
+            server_name, hours, state.message
+        ));
+    }
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(maintenance on <server|category> [message]) | (maintenance off <server|category>) | (maintenance list)");
+}
+
+/// A server in maintenance, and what to restore when it ends.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MaintenanceState {
+    /// The contents of `whitelist.json` before maintenance
+    saved_whitelist: String,
+    /// Whether the whitelist was turned on before maintenance
+    whitelist_enforced: bool,
+    message: String,
+    since: i64,
+}
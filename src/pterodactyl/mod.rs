@@ -1,48 +1,429 @@
-use pterodactyl_api::client::ServerState;
-use serde::{Deserialize, Serialize};
-use serenity::model::id::ChannelId;
-use std::collections::BTreeMap;
+use crate::command_sender::CommandSender;
+use crate::config;
+use futures::future::try_join_all;
+use git_version::git_version;
+use log::error;
+use pterodactyl_api::client::{Client, ClientBuilder, Server, ServerState};
+use serde::{Deserialize, Deserializer, Serialize};
+use serenity::model::id::{ChannelId, RoleId};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::future::Future;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use uuid::Uuid;
 
+pub mod bans;
+pub mod console_query;
+pub mod console_rules;
+pub mod discovery;
+pub mod game_commands;
+pub mod lag;
+pub mod maintenance;
+pub mod online_players;
+pub mod ops;
 pub mod perms_sync;
+pub mod playtime;
+pub mod resources;
+pub mod role_whitelist;
 pub mod smp_commands;
 pub mod whitelist;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct PterodactylServer {
+    /// The name of the panel the server is hosted on
+    #[serde(default = "default_panel_name")]
+    pub panel: String,
     pub id: String,
     pub name: String,
     pub display_name: String,
     pub category: PterodactylServerCategory,
     #[serde(default)]
     pub allow_commands: bool,
+    /// The directory schematics may be uploaded to from Discord, relative to the server root.
+    /// Uploads are disabled if this is not set.
+    #[serde(default)]
+    pub schematics_directory: Option<String>,
+}
+
+/// The name of the panel configured by `pterodactyl_domain` and `pterodactyl_api_key`.
+pub const DEFAULT_PANEL_NAME: &str = "default";
+
+fn default_panel_name() -> String {
+    DEFAULT_PANEL_NAME.to_owned()
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum PterodactylServerCategory {
-    Smp,
-    Cmp,
-    Copy,
-    Patreon,
-    Protobot,
-    OtherTechServer,
+impl PterodactylServer {
+    /// The configured schematics directory without a trailing slash, if it stays within the
+    /// server root.
+    pub fn schematics_directory(&self) -> Option<&str> {
+        let directory = self.schematics_directory.as_deref()?.trim_end_matches('/');
+        let is_valid = !directory.is_empty()
+            && !directory.starts_with('/')
+            && directory
+                .split('/')
+                .all(|component| !component.is_empty() && component != "." && component != "..");
+        is_valid.then_some(directory)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PterodactylPanel {
+    pub domain: String,
+    pub api_key: String,
+}
+
+/// API clients for every configured panel, keyed by panel name. Panels are only read from the
+/// config on startup, so `reload` warns about panel changes.
+pub struct PterodactylPanels {
+    panels: BTreeMap<String, Client>,
+}
+
+impl PterodactylPanels {
+    pub fn from_config(config: &config::Config) -> Self {
+        let panels = config
+            .pterodactyl_panels()
+            .map(|(name, panel)| {
+                (
+                    name.to_owned(),
+                    ClientBuilder::new(&panel.domain, &panel.api_key).build(),
+                )
+            })
+            .collect();
+        Self { panels }
+    }
+
+    pub(crate) fn get(&self, panel: &str) -> crate::Result<&Client> {
+        self.panels
+            .get(panel)
+            .ok_or_else(|| crate::Error::Other(format!("Unknown panel {panel}")))
+    }
+
+    pub(crate) fn get_server(&self, server: &PterodactylServer) -> crate::Result<Server<'_>> {
+        Ok(self.get(&server.panel)?.get_server(&server.id))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Client)> {
+        self.panels
+            .iter()
+            .map(|(name, client)| (name.as_str(), client))
+    }
 }
 
+/// The name of a server category. Categories and their capabilities are defined in the
+/// `pterodactyl_categories` config table.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(transparent)]
+pub struct PterodactylServerCategory(&'static str);
+
 impl PterodactylServerCategory {
+    pub const SMP: Self = Self("smp");
+    pub const CMP: Self = Self("cmp");
+    pub const COPY: Self = Self("copy");
+    pub const PATREON: Self = Self("patreon");
+    pub const PROTOBOT: Self = Self("protobot");
+    pub const OTHER_TECH_SERVER: Self = Self("other_tech_server");
+
+    /// Category names are interned so that the type can be `Copy`, only leaking each distinct name
+    /// once across config reloads.
+    pub fn new(name: &str) -> Self {
+        static NAMES: OnceLock<Mutex<HashSet<&'static str>>> = OnceLock::new();
+        let mut names = NAMES.get_or_init(Default::default).lock().unwrap();
+        match names.get(name) {
+            Some(name) => Self(name),
+            None => {
+                let name: &'static str = Box::leak(name.into());
+                names.insert(name);
+                Self(name)
+            }
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+
+    pub fn info(&self) -> PterodactylCategoryInfo {
+        config::get().category_info(*self)
+    }
+
     pub fn is_proto(&self) -> bool {
-        *self != Self::OtherTechServer
+        self.info().proto
     }
 
     pub fn is_minecraft(&self) -> bool {
-        match self {
-            Self::Smp | Self::Cmp | Self::Copy | Self::Patreon | Self::OtherTechServer => true,
-            Self::Protobot => false,
-        }
+        self.info().minecraft
     }
 
     pub fn should_be_opped(&self) -> bool {
-        matches!(self, Self::Cmp | Self::Copy)
+        self.info().opped
+    }
+
+    pub fn is_bridgeable(&self) -> bool {
+        self.info().bridgeable
+    }
+
+    pub fn is_whitelist_managed(&self) -> bool {
+        self.info().whitelist_managed
+    }
+}
+
+impl Debug for PterodactylServerCategory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for PterodactylServerCategory {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::new(&String::deserialize(deserializer)?))
+    }
+}
+
+/// The capabilities of a server category.
+#[derive(Debug, Default, Copy, Clone, Deserialize)]
+pub struct PterodactylCategoryInfo {
+    /// Whether servers of this category are Minecraft servers, which get a console listener
+    #[serde(default)]
+    pub minecraft: bool,
+    /// Whether servers of this category are ours, and have their panel perms synced
+    #[serde(default)]
+    pub proto: bool,
+    /// Whether whitelisted players are also opped
+    #[serde(default)]
+    pub opped: bool,
+    /// Whether servers of this category can be part of a chat bridge
+    #[serde(default)]
+    pub bridgeable: bool,
+    /// Whether player management commands (whitelist, ops, bans) can operate on this category
+    #[serde(default)]
+    pub whitelist_managed: bool,
+}
+
+/// The categories used before they were configurable.
+pub(crate) fn default_categories() -> BTreeMap<PterodactylServerCategory, PterodactylCategoryInfo> {
+    let managed = PterodactylCategoryInfo {
+        minecraft: true,
+        proto: true,
+        opped: false,
+        bridgeable: true,
+        whitelist_managed: true,
+    };
+    BTreeMap::from([
+        (PterodactylServerCategory::SMP, managed),
+        (
+            PterodactylServerCategory::CMP,
+            PterodactylCategoryInfo {
+                opped: true,
+                ..managed
+            },
+        ),
+        (
+            PterodactylServerCategory::COPY,
+            PterodactylCategoryInfo {
+                opped: true,
+                ..managed
+            },
+        ),
+        (PterodactylServerCategory::PATREON, managed),
+        (
+            PterodactylServerCategory::PROTOBOT,
+            PterodactylCategoryInfo {
+                proto: true,
+                ..Default::default()
+            },
+        ),
+        (
+            PterodactylServerCategory::OTHER_TECH_SERVER,
+            PterodactylCategoryInfo {
+                minecraft: true,
+                bridgeable: true,
+                ..Default::default()
+            },
+        ),
+    ])
+}
+
+/// Parses a category that player management commands (whitelist, ops, etc.) can operate on.
+pub(crate) fn parse_managed_category(
+    sender: &CommandSender,
+    category: &str,
+) -> Option<PterodactylServerCategory> {
+    let config = config::get();
+    let Some((&category, info)) = config
+        .pterodactyl_categories
+        .iter()
+        .find(|(name, _)| name.name() == category)
+    else {
+        sender.error(format!("Unknown category {category}"));
+        return None;
+    };
+    if !info.whitelist_managed {
+        sender.error(format!("Players on {category:?} servers are not managed"));
+        return None;
+    }
+    Some(category)
+}
+
+/// Runs an operation on the given category, or on every managed category if `category` is `all`.
+pub(crate) async fn run_across_categories<F, Fut>(
+    sender: &CommandSender,
+    category: &str,
+    mut operation: F,
+) -> crate::Result<()>
+where
+    F: FnMut(PterodactylServerCategory) -> Fut,
+    Fut: Future<Output = crate::Result<()>>,
+{
+    if category == "all" {
+        let categories: BTreeSet<_> = config::get()
+            .pterodactyl_servers
+            .iter()
+            .map(|server| server.category)
+            .filter(|category| category.is_whitelist_managed())
+            .collect();
+        try_join_all(categories.into_iter().map(operation)).await?;
+    } else {
+        let Some(category) = parse_managed_category(sender, category) else {
+            return Ok(());
+        };
+        operation(category).await?;
+    }
+    Ok(())
+}
+
+/// An HTTP client identifying itself as protobot, for APIs we call directly.
+pub(crate) fn http_client() -> crate::Result<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent(format!("protobot {}", git_version!()))
+        .build()?)
+}
+
+/// Looks up the correctly capitalized name and the UUID of a Minecraft player from Mojang.
+pub(crate) async fn lookup_player(player_name: &str) -> crate::Result<(String, Uuid)> {
+    let response = http_client()?
+        .post("https://api.minecraftservices.com/minecraft/profile/lookup/bulk/byname")
+        .header("Accept", "application/json")
+        .header("Content-Type", "application/json")
+        .json(&vec![player_name])
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        error!(
+            "Failed to request UUID: {}, {}",
+            status,
+            response.text().await?
+        );
+        return Err(crate::Error::Other("failed to request UUID".into()));
+    }
+
+    #[derive(Deserialize)]
+    struct MojangPlayer {
+        id: Uuid,
+        name: String,
+    }
+
+    let mojang_players = response.json::<Vec<MojangPlayer>>().await?;
+    if mojang_players.len() != 1 {
+        return Err(crate::Error::Other(
+            "Mojang server didn't return 1 player when requested".to_owned(),
+        ));
+    }
+
+    let player = mojang_players.into_iter().next().unwrap();
+    Ok((player.name, player.id))
+}
+
+/// Reads a JSON file from the first server of a category. Servers within a category are kept in
+/// sync, so the first server is treated as the source of truth.
+pub(crate) async fn read_category_json<T>(
+    data: &crate::ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+    file: &str,
+) -> crate::Result<Option<T>>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let config = config::get();
+    let Some(server) = config.pterodactyl_servers(category).next() else {
+        sender.error("No servers of the given category");
+        return Ok(None);
+    };
+    let json = data
+        .pterodactyl
+        .get_server(server)?
+        .file_contents_text(file)
+        .await?;
+    Ok(Some(serde_json::from_str(&json)?))
+}
+
+/// Parses a duration such as `30m`, `12h`, `7d` or `1w2d`.
+pub(crate) fn parse_duration(duration: &str) -> Option<chrono::Duration> {
+    let mut total = chrono::Duration::zero();
+    let mut rest = duration;
+    while !rest.is_empty() {
+        let unit_index = rest.find(|char: char| !char.is_ascii_digit())?;
+        let (amount, unit) = rest.split_at(unit_index);
+        let amount: i64 = amount.parse().ok()?;
+        let unit_len = unit.chars().next()?.len_utf8();
+        total += match &unit[..unit_len] {
+            "s" => chrono::Duration::try_seconds(amount)?,
+            "m" => chrono::Duration::try_minutes(amount)?,
+            "h" => chrono::Duration::try_hours(amount)?,
+            "d" => chrono::Duration::try_days(amount)?,
+            "w" => chrono::Duration::try_weeks(amount)?,
+            _ => return None,
+        };
+        rest = &unit[unit_len..];
     }
+    (total > chrono::Duration::zero()).then_some(total)
+}
+
+/// Whether a console message was written by a player: chat (`<name>`, or `[Not Secure] <name>`),
+/// `/me` (`* name`), `/say` and team messages (`[name]`), or anything from a chat thread.
+pub(crate) fn is_chat(thread: &str, message: &str) -> bool {
+    message.starts_with(['<', '*', '[']) || thread.contains("Chat")
+}
+
+/// Like `is_chat`, for a whole `[time] [thread/LEVEL]: message` console line.
+pub(crate) fn is_chat_line(line: &str) -> bool {
+    line.split_once("]: ")
+        .is_some_and(|(prefix, message)| is_chat(prefix, message))
+}
+
+/// The server saves its in-memory player lists (ops, bans) whenever a command changes them. Wait for
+/// that to happen before writing our own list file, so that our changes aren't overwritten.
+pub(crate) const LIST_FILE_WRITE_DELAY: Duration = Duration::from_secs(1);
+
+/// Writes a player list file such as `ops.json` to every server in a category. Running servers only
+/// load these files on startup, so the given command is run first to apply the change immediately.
+pub(crate) async fn write_list_file(
+    data: &crate::ProtobotData,
+    category: PterodactylServerCategory,
+    file: &str,
+    list_json: String,
+    command: String,
+    on_success: impl Fn(&str),
+) -> crate::Result<()> {
+    let config = config::get();
+    let tasks = config.pterodactyl_servers(category).map(|server| {
+        let list_json = list_json.clone();
+        let command = command.clone();
+        let on_success = &on_success;
+        async move {
+            let ptero_server = data.pterodactyl.get_server(server)?;
+            send_command_safe(&ptero_server, command).await?;
+            tokio::time::sleep(LIST_FILE_WRITE_DELAY).await;
+            ptero_server.write_file(file, list_json).await?;
+            on_success(&server.name);
+            Ok::<(), crate::Error>(())
+        }
+    });
+    try_join_all(tasks).await?;
+    Ok(())
 }
 
 pub trait PterodactylServerCategoryFilter {
@@ -70,37 +451,120 @@ where
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Statically configured panel users, in addition to those derived from `PterodactylTierRoles`.
+#[derive(Debug, Default, Deserialize)]
 pub struct PterodactylEmails {
+    #[serde(default)]
     pub superadmin: Vec<String>,
+    #[serde(default)]
     pub admin: Vec<String>,
+    #[serde(default)]
     pub normal: Vec<String>,
+    #[serde(default)]
     pub ignore: Vec<String>,
 }
 
+/// The Discord roles granting each panel access tier to members who have registered a panel email.
+#[derive(Debug, Default, Deserialize)]
+pub struct PterodactylTierRoles {
+    #[serde(default)]
+    pub superadmin: Vec<RoleId>,
+    #[serde(default)]
+    pub admin: Vec<RoleId>,
+    #[serde(default)]
+    pub normal: Vec<RoleId>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PanelTier {
+    Superadmin,
+    Admin,
+    Normal,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PterodactylAllPerms {
     pub superadmin: PterodactylPerms,
     pub admin: PterodactylPerms,
     pub normal: PterodactylPerms,
+    /// Adjustments to the perms of individual users, keyed by email
+    #[serde(default)]
+    pub users: BTreeMap<String, Vec<PterodactylUserPerms>>,
+}
+
+impl PterodactylAllPerms {
+    pub fn tier(&self, tier: PanelTier) -> &PterodactylPerms {
+        match tier {
+            PanelTier::Superadmin => &self.superadmin,
+            PanelTier::Admin => &self.admin,
+            PanelTier::Normal => &self.normal,
+        }
+    }
+
+    pub fn user_overrides<'a>(
+        &'a self,
+        email: &'a str,
+        server: &'a PterodactylServer,
+    ) -> impl Iterator<Item = &'a PterodactylUserPerms> {
+        self.users
+            .iter()
+            .filter(move |(user_email, _)| user_email.eq_ignore_ascii_case(email))
+            .flat_map(|(_, overrides)| overrides)
+            .filter(|user_perms| {
+                user_perms.servers.is_empty() || user_perms.servers.contains(&server.name)
+            })
+    }
+
+    /// Resolves the perms of a user on a server. The tier's perms are taken from the server
+    /// override if there is one, otherwise the category override, otherwise the default. User
+    /// overrides are then applied on top.
+    pub fn effective_perms(
+        &self,
+        tier: PanelTier,
+        server: &PterodactylServer,
+        email: &str,
+    ) -> BTreeSet<String> {
+        let mut perms: BTreeSet<_> = self.tier(tier).get_perms(server).iter().cloned().collect();
+        for user_perms in self.user_overrides(email, server) {
+            perms.extend(user_perms.add.iter().cloned());
+            perms.retain(|perm| !user_perms.remove.contains(perm));
+        }
+        perms
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct PterodactylPerms {
     default: Vec<String>,
     #[serde(default)]
-    overrides: BTreeMap<PterodactylServerCategory, Vec<String>>,
+    pub(crate) overrides: BTreeMap<PterodactylServerCategory, Vec<String>>,
+    #[serde(default)]
+    pub(crate) server_overrides: BTreeMap<String, Vec<String>>,
 }
 
 impl PterodactylPerms {
-    pub fn get_perms(&self, category: PterodactylServerCategory) -> &[String] {
-        match self.overrides.get(&category) {
+    pub fn get_perms(&self, server: &PterodactylServer) -> &[String] {
+        if let Some(overrides) = self.server_overrides.get(&server.name) {
+            return overrides;
+        }
+        match self.overrides.get(&server.category) {
             Some(overrides) => overrides,
             None => &self.default,
         }
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct PterodactylUserPerms {
+    /// The servers this applies to, or all servers if empty
+    #[serde(default)]
+    pub servers: Vec<String>,
+    #[serde(default)]
+    pub add: Vec<String>,
+    #[serde(default)]
+    pub remove: Vec<String>,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct PterodactylChatBridge {
     pub discord_channels: Vec<PterodactylChatBridgeDiscordChannel>,
@@ -128,6 +592,15 @@ pub async fn send_command_safe(
 pub async fn tellraw(
     server: &pterodactyl_api::client::Server<'_>,
     message: impl Into<String>,
+) -> crate::Result<()> {
+    tellraw_to(server, "@a", message).await
+}
+
+/// Sends a message to the players matching a player name or target selector.
+pub async fn tellraw_to(
+    server: &pterodactyl_api::client::Server<'_>,
+    target: &str,
+    message: impl Into<String>,
 ) -> crate::Result<()> {
     #[derive(Serialize)]
     struct TextComponent {
@@ -136,5 +609,5 @@ pub async fn tellraw(
     let text_component = serde_json::to_string(&TextComponent {
         text: message.into(),
     })?;
-    send_command_safe(server, format!("tellraw @a {text_component}")).await
+    send_command_safe(server, format!("tellraw {target} {text_component}")).await
 }
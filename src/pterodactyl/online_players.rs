@@ -0,0 +1,45 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Mutex, OnceLock};
+
+/// The players online on each server by server name, tracked from join and leave messages in the
+/// console.
+fn online_players_by_server() -> &'static Mutex<HashMap<String, BTreeSet<String>>> {
+    static ONLINE_PLAYERS: OnceLock<Mutex<HashMap<String, BTreeSet<String>>>> = OnceLock::new();
+    ONLINE_PLAYERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+pub(crate) fn player_joined(server_name: &str, player: &str) {
+    online_players_by_server()
+        .lock()
+        .unwrap()
+        .entry(server_name.to_owned())
+        .or_default()
+        .insert(player.to_owned());
+}
+
+pub(crate) fn player_left(server_name: &str, player: &str) {
+    if let Some(players) = online_players_by_server()
+        .lock()
+        .unwrap()
+        .get_mut(server_name)
+    {
+        players.remove(player);
+    }
+}
+
+/// Called when a server stops or starts, as players don't get leave messages when it crashes.
+pub(crate) fn server_restarted(server_name: &str) {
+    online_players_by_server()
+        .lock()
+        .unwrap()
+        .remove(server_name);
+}
+
+pub(crate) fn online_players(server_name: &str) -> Vec<String> {
+    online_players_by_server()
+        .lock()
+        .unwrap()
+        .get(server_name)
+        .map(|players| players.iter().cloned().collect())
+        .unwrap_or_default()
+}
@@ -0,0 +1,421 @@
+use crate::command_sender::CommandSender;
+use crate::pterodactyl::{
+    lookup_player, parse_managed_category, read_category_json, run_across_categories,
+    send_command_safe, write_list_file, PterodactylServerCategory, LIST_FILE_WRITE_DELAY,
+};
+use crate::{config, ProtobotData};
+use futures::future::try_join_all;
+use pterodactyl_api::client::ServerState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+pub(crate) const DEFAULT_OP_LEVEL: u8 = 4;
+
+pub(crate) async fn run(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    let Some(operation) = args.next() else {
+        print_usage(sender);
+        return Ok(());
+    };
+    match operation {
+        "add" => {
+            let Some(player) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let level = match args.next() {
+                None | Some("default") => None,
+                Some(level) => match level.parse::<u8>() {
+                    Ok(level) if (1..=4).contains(&level) => Some(level),
+                    _ => {
+                        sender.error("Op level must be between 1 and 4");
+                        return Ok(());
+                    }
+                },
+            };
+            let Ok(bypasses_player_limit) = args.next().map_or(Ok(false), str::parse::<bool>)
+            else {
+                sender.error("bypassesPlayerLimit must be true or false");
+                return Ok(());
+            };
+            let name_and_uuid = OnceCell::new();
+            run_across_categories(sender, category, |category| {
+                let name_and_uuid = &name_and_uuid;
+                async move {
+                    let (player_name, player_uuid) = name_and_uuid
+                        .get_or_try_init(|| lookup_player(player))
+                        .await?;
+                    op_player(
+                        data,
+                        sender,
+                        player_name,
+                        *player_uuid,
+                        category,
+                        level.unwrap_or_else(|| config::get().default_op_level(category)),
+                        bypasses_player_limit,
+                    )
+                    .await
+                }
+            })
+            .await?;
+        }
+        "remove" => {
+            let Some(player) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            run_across_categories(sender, category, |category| {
+                deop_player(data, sender, player, category)
+            })
+            .await?;
+        }
+        "list" => {
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = parse_managed_category(sender, category) else {
+                return Ok(());
+            };
+            ops_list(data, sender, category).await?;
+        }
+        "sync" => {
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            run_across_categories(sender, category, |category| {
+                ops_sync(data, sender, category)
+            })
+            .await?;
+        }
+        _ => {
+            print_usage(sender);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn op_player(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    player_name: &str,
+    player_uuid: Uuid,
+    category: PterodactylServerCategory,
+    level: u8,
+    bypasses_player_limit: bool,
+) -> crate::Result<()> {
+    let Some(mut ops) = get_ops(data, sender, category).await? else {
+        return Ok(());
+    };
+
+    ops.retain(|op| op.uuid != player_uuid);
+    ops.push(Op {
+        uuid: player_uuid,
+        name: player_name.to_owned(),
+        level,
+        bypasses_player_limit,
+    });
+    ops.sort_by_key(|op| op.name.to_ascii_lowercase());
+
+    let config = config::get();
+    let live_levels: HashMap<_, _> = try_join_all(config.pterodactyl_servers(category).map(
+        |server| async move {
+            let live_level = live_op_level(&data.pterodactyl.get_server(server)?).await?;
+            Ok::<_, crate::Error>((server.name.as_str(), live_level))
+        },
+    ))
+    .await?
+    .into_iter()
+    .collect();
+
+    set_ops(data, ops, category, format!("op {player_name}"), |server| {
+        match live_levels.get(server).copied().flatten() {
+            Some(live_level) if live_level != level || bypasses_player_limit => {
+                sender.info(format!(
+                    "Opped {player_name} on {server} with level {live_level} for now. Level {level}{} is saved and takes effect when {server} restarts, unless ops are changed in game before then",
+                    if bypasses_player_limit {
+                        " and bypassing the player limit"
+                    } else {
+                        ""
+                    }
+                ))
+            }
+            _ => sender.info(format!(
+                "Opped {player_name} on {server} with level {level}"
+            )),
+        }
+    })
+    .await
+}
+
+/// Ops a newly whitelisted player with the category's default level. Players who are already opped
+/// keep their level and whether they bypass the player limit, which only `ops add` changes.
+pub(crate) async fn op_whitelisted_player(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    player_name: &str,
+    player_uuid: Uuid,
+    category: PterodactylServerCategory,
+) -> crate::Result<()> {
+    let Some(ops) = get_ops(data, sender, category).await? else {
+        return Ok(());
+    };
+    if let Some(op) = ops.iter().find(|op| op.uuid == player_uuid) {
+        sender.info(format!(
+            "{} is already opped on {:?} with level {}, which is kept",
+            player_name, category, op.level
+        ));
+        return Ok(());
+    }
+    op_player(
+        data,
+        sender,
+        player_name,
+        player_uuid,
+        category,
+        config::get().default_op_level(category),
+        false,
+    )
+    .await
+}
+
+/// Returns the level `op` grants on a server if it is running. Running servers only load levels
+/// from `ops.json` on startup, so any other level is pending until the server restarts.
+async fn live_op_level(
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+) -> crate::Result<Option<u8>> {
+    if ptero_server.get_resources().await?.current_state != ServerState::Running {
+        return Ok(None);
+    }
+    let level = ptero_server
+        .file_contents_text("server.properties")
+        .await?
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("op-permission-level=")?
+                .trim()
+                .parse()
+                .ok()
+        })
+        .unwrap_or(DEFAULT_OP_LEVEL);
+    Ok(Some(level))
+}
+
+pub(crate) async fn deop_player(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    player_name: &str,
+    category: PterodactylServerCategory,
+) -> crate::Result<()> {
+    let Some(mut ops) = get_ops(data, sender, category).await? else {
+        return Ok(());
+    };
+
+    let num_ops = ops.len();
+    ops.retain(|op| !op.name.eq_ignore_ascii_case(player_name));
+    if ops.len() == num_ops {
+        sender.error(format!("{player_name} was not opped"));
+        return Ok(());
+    }
+
+    set_ops(
+        data,
+        ops,
+        category,
+        format!("deop {player_name}"),
+        |server| sender.info(format!("De-opped {player_name} on {server}")),
+    )
+    .await
+}
+
+/// Returns the op level of a player on a server, or 0 if they are not an op.
+pub(crate) async fn op_level(
+    ptero_server: &pterodactyl_api::client::Server<'_>,
+    player_name: &str,
+) -> crate::Result<u8> {
+    let ops: Vec<Op> = serde_json::from_str(&ptero_server.file_contents_text("ops.json").await?)?;
+    Ok(ops
+        .iter()
+        .find(|op| op.name.eq_ignore_ascii_case(player_name))
+        .map_or(0, |op| op.level))
+}
+
+async fn ops_list(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+) -> crate::Result<()> {
+    let Some(ops) = get_ops(data, sender, category).await? else {
+        return Ok(());
+    };
+    if ops.is_empty() {
+        sender.info("There are no ops");
+    } else {
+        sender.info(format!(
+            "There are {} ops: {}",
+            ops.len(),
+            ops.iter()
+                .map(|op| {
+                    let mut desc = format!("{} (level {}", op.name, op.level);
+                    if op.bypasses_player_limit {
+                        desc += ", bypasses player limit";
+                    }
+                    desc + ")"
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Makes the ops of every server in a category match the first server of the category.
+async fn ops_sync(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+) -> crate::Result<()> {
+    let config = config::get();
+    let mut servers = config.pterodactyl_servers(category);
+    let Some(source_server) = servers.next() else {
+        sender.error("No servers of the given category");
+        return Ok(());
+    };
+    let source_ops: Vec<Op> = serde_json::from_str(
+        &data
+            .pterodactyl
+            .get_server(source_server)?
+            .file_contents_text("ops.json")
+            .await?,
+    )?;
+    let source_ops_json = serde_json::to_string_pretty(&source_ops)?;
+
+    try_join_all(servers.map(|server| {
+        let source_ops = &source_ops;
+        let source_ops_json = source_ops_json.clone();
+        async move {
+            let ptero_server = data.pterodactyl.get_server(server)?;
+            let server_ops: Vec<Op> =
+                serde_json::from_str(&ptero_server.file_contents_text("ops.json").await?)?;
+
+            let live_level = live_op_level(&ptero_server).await?;
+            let mut changes = Vec::new();
+            let mut commands = Vec::new();
+            let mut pending_restart = false;
+            for op in source_ops {
+                match server_ops
+                    .iter()
+                    .find(|server_op| server_op.uuid == op.uuid)
+                {
+                    None => {
+                        changes.push(format!("+{} (level {})", op.name, op.level));
+                        commands.push(format!("op {}", op.name));
+                        pending_restart |= live_level.is_some_and(|live_level| {
+                            live_level != op.level || op.bypasses_player_limit
+                        });
+                    }
+                    Some(server_op) if server_op != op => {
+                        changes.push(format!(
+                            "~{} (level {} -> {})",
+                            op.name, server_op.level, op.level
+                        ));
+                        // There is no command to change the level of an op
+                        pending_restart |= live_level.is_some();
+                    }
+                    Some(_) => {}
+                }
+            }
+            for server_op in &server_ops {
+                if !source_ops.iter().any(|op| op.uuid == server_op.uuid) {
+                    changes.push(format!("-{}", server_op.name));
+                    commands.push(format!("deop {}", server_op.name));
+                }
+            }
+
+            if changes.is_empty() {
+                sender.info(format!(
+                    "Ops on {} already match {}",
+                    server.name, source_server.name
+                ));
+                return Ok(());
+            }
+
+            for command in commands {
+                send_command_safe(&ptero_server, command).await?;
+            }
+            tokio::time::sleep(LIST_FILE_WRITE_DELAY).await;
+            ptero_server.write_file("ops.json", source_ops_json).await?;
+            sender.info(format!(
+                "Synced ops on {} from {}: {}",
+                server.name,
+                source_server.name,
+                changes.join(", ")
+            ));
+            if pending_restart {
+                sender.info(format!(
+                    "Op levels on {} take effect when it restarts, unless ops are changed in game before then",
+                    server.name
+                ));
+            }
+            Ok::<(), crate::Error>(())
+        }
+    }))
+    .await?;
+
+    Ok(())
+}
+
+async fn get_ops(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+) -> crate::Result<Option<Vec<Op>>> {
+    read_category_json(data, sender, category, "ops.json").await
+}
+
+/// Writes the ops list to every server in a category. Op levels that differ from the server's
+/// `op-permission-level` only take effect after a restart.
+async fn set_ops(
+    data: &ProtobotData,
+    ops: Vec<Op>,
+    category: PterodactylServerCategory,
+    command: String,
+    on_success: impl Fn(&str),
+) -> crate::Result<()> {
+    write_list_file(
+        data,
+        category,
+        "ops.json",
+        serde_json::to_string_pretty(&ops)?,
+        command,
+        on_success,
+    )
+    .await
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(ops add <player> <category|all> [level|default] [bypassesPlayerLimit]) | (ops remove <player> <category|all>) | (ops list <category>) | (ops sync <category|all>)");
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Op {
+    uuid: Uuid,
+    name: String,
+    level: u8,
+    bypasses_player_limit: bool,
+}
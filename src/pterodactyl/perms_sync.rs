@@ -1,144 +1,574 @@
-use crate::pterodactyl::PterodactylServer;
+use crate::command_sender::CommandSender;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::{PanelTier, PterodactylServer};
 use crate::{config, ProtobotData};
-use log::{error, info};
-use pterodactyl_api::client::Server;
-use std::collections::HashSet;
+use futures::StreamExt;
+use log::{error, warn};
+use serenity::builder::{CreateAttachment, CreateMessage};
+use serenity::model::id::{RoleId, UserId};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
+/// How long a planned sync can be confirmed for before it has to be planned again
+const PLAN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How often to check whether periodic perms sync has been enabled by a config reload
+const PERIODIC_SYNC_DISABLED_RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+fn pending_plan() -> &'static Mutex<Option<PendingPlan>> {
+    static PENDING_PLAN: OnceLock<Mutex<Option<PendingPlan>>> = OnceLock::new();
+    PENDING_PLAN.get_or_init(|| Mutex::new(None))
+}
+
 pub(crate) async fn run(
     data: &ProtobotData,
-    mut args: impl Iterator<Item = &str>,
+    sender: &CommandSender,
+    args: impl Iterator<Item = &str>,
 ) -> crate::Result<()> {
-    let Some(server_name) = args.next() else {
-        error!("Missing server argument");
+    let mut dry_run = false;
+    let args: Vec<_> = args
+        .filter(|arg| {
+            if *arg == "--dry-run" {
+                dry_run = true;
+                false
+            } else {
+                true
+            }
+        })
+        .collect();
+
+    let Some(&server_name) = args.first() else {
+        print_usage(sender);
         return Ok(());
     };
 
+    match server_name {
+        "confirm" => return confirm(data, sender).await,
+        "effective" => {
+            let (Some(email), Some(server_name)) = (args.get(1), args.get(2)) else {
+                print_usage(sender);
+                return Ok(());
+            };
+            return show_effective_perms(data, sender, email, server_name).await;
+        }
+        "cancel" => {
+            if pending_plan().lock().unwrap().take().is_some() {
+                sender.info("Cancelled the pending perms sync");
+            } else {
+                sender.error("There is no pending perms sync");
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
     let config = config::get();
 
+    let mut plans = Vec::new();
     if server_name == "all" {
-        for server in &config.pterodactyl_servers {
-            if server.category.is_proto() {
-                run_on_server(data, server).await?;
-            }
-        }
+        plans = plan_all(data, sender).await?;
     } else {
         let Some(server) = config
             .pterodactyl_servers
             .iter()
             .find(|s| s.name == server_name)
         else {
-            error!("Unknown server: {}", server_name);
+            sender.error(format!("Unknown server: {}", server_name));
             return Ok(());
         };
         if !server.category.is_proto() {
-            error!("Cannot run perms sync on non-proto server: {}", server_name);
+            sender.error(format!(
+                "Cannot run perms sync on non-proto server: {}",
+                server_name
+            ));
             return Ok(());
         }
-        run_on_server(data, server).await?;
+        let tiers = compute_tiers(data, sender).await?;
+        plans.push(plan_server(data, &tiers, server).await?);
     }
 
-    info!("Successfully synced perms");
+    plans.retain(|plan| !plan.changes.is_empty());
+    if plans.is_empty() {
+        sender.info("Perms are already in sync");
+        return Ok(());
+    }
+
+    for plan in &plans {
+        plan.print(sender);
+    }
+
+    if dry_run {
+        sender.info("Dry run, no changes were made");
+    } else {
+        *pending_plan().lock().unwrap() = Some(PendingPlan {
+            plans,
+            created: Instant::now(),
+        });
+        sender.info(format!(
+            "Run \"perms_sync confirm\" within {} minutes to apply these changes, or \"perms_sync cancel\" to discard them",
+            PLAN_TIMEOUT.as_secs() / 60
+        ));
+    }
 
     Ok(())
 }
 
-async fn run_on_server(data: &ProtobotData, server: &PterodactylServer) -> crate::Result<()> {
-    let config = config::get();
+async fn confirm(data: &ProtobotData, sender: &CommandSender) -> crate::Result<()> {
+    let Some(pending) = pending_plan().lock().unwrap().take() else {
+        sender.error("There is no pending perms sync");
+        return Ok(());
+    };
+    if pending.created.elapsed() > PLAN_TIMEOUT {
+        sender.error("The pending perms sync has expired, run it again to re-plan");
+        return Ok(());
+    }
 
-    let mut remaining_superadmins: HashSet<_> =
-        config.pterodactyl_emails.superadmin.iter().collect();
-    let mut remaining_admins: HashSet<_> = config.pterodactyl_emails.admin.iter().collect();
-    let mut remaining_panel_access: HashSet<_> = config.pterodactyl_emails.normal.iter().collect();
-    let ignored_emails: HashSet<_> = config.pterodactyl_emails.ignore.iter().collect();
+    for plan in &pending.plans {
+        plan.apply(data).await?;
+        sender.info(format!("Applied perms changes on {}", plan.server.name));
+    }
 
-    let superadmin_perms: HashSet<_> = config
-        .pterodactyl_perms
-        .superadmin
-        .get_perms(server.category)
-        .iter()
-        .collect();
-    let admin_perms: HashSet<_> = config
-        .pterodactyl_perms
-        .admin
-        .get_perms(server.category)
-        .iter()
-        .collect();
-    let panel_access_perms: HashSet<_> = config
-        .pterodactyl_perms
-        .normal
-        .get_perms(server.category)
-        .iter()
-        .collect();
+    sender.info("Successfully synced perms");
+    Ok(())
+}
 
-    let server = data.pterodactyl.get_server(&server.id);
-    let existing_users = server.list_users().await?;
-    for existing_user in existing_users {
-        if remaining_superadmins.remove(&existing_user.email) {
-            if existing_user.permissions.iter().collect::<HashSet<_>>() != superadmin_perms {
-                set_user_perms(&server, existing_user.uuid, &superadmin_perms).await?;
+pub(crate) async fn run_panel_emails(
+    _data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    let guild_id = config::get().guild_id;
+    match args.next() {
+        Some("list") => {
+            let storage = GuildStorage::get(guild_id).await;
+            if storage.panel_emails.is_empty() {
+                sender.info("No panel emails have been registered");
             }
-        } else if remaining_admins.remove(&existing_user.email) {
-            if existing_user.permissions.iter().collect::<HashSet<_>>() != admin_perms {
-                set_user_perms(&server, existing_user.uuid, &admin_perms).await?;
+            for (user_id, email) in &storage.panel_emails {
+                sender.info(format!("{user_id}: {email}"));
             }
-        } else if remaining_panel_access.remove(&existing_user.email) {
-            if existing_user.permissions.iter().collect::<HashSet<_>>() != panel_access_perms {
-                set_user_perms(&server, existing_user.uuid, &panel_access_perms).await?;
+        }
+        Some("remove") => {
+            let Some(user_id) = args
+                .next()
+                .and_then(|user_id| user_id.parse::<UserId>().ok())
+            else {
+                sender.error("Missing or invalid user ID");
+                return Ok(());
+            };
+            let mut storage = GuildStorage::get_mut(guild_id).await;
+            match storage.panel_emails.remove(&user_id) {
+                Some(email) => {
+                    storage.save().await;
+                    sender.info(format!("Removed panel email {email} of user {user_id}"));
+                }
+                None => {
+                    storage.discard();
+                    sender.error(format!("User {user_id} has no registered panel email"));
+                }
             }
-        } else if !ignored_emails.contains(&existing_user.email) {
-            server.delete_user(existing_user.uuid).await?;
         }
+        _ => sender.info("(panel_emails list) | (panel_emails remove <user id>)"),
     }
+    Ok(())
+}
 
-    for superadmin in remaining_superadmins {
-        if !superadmin_perms.is_empty() {
-            server
-                .add_user(
-                    superadmin,
-                    superadmin_perms.iter().map(|str| (*str).clone()).collect(),
-                )
-                .await?;
+/// Periodically syncs perms on every proto server, alerting staff about any subusers that had
+/// drifted from the config.
+pub(crate) async fn run_periodic(data: ProtobotData) {
+    loop {
+        let interval = config::get()
+            .periodic_perms_sync
+            .as_ref()
+            .map_or(PERIODIC_SYNC_DISABLED_RECHECK_INTERVAL, |periodic_sync| {
+                Duration::from_secs(periodic_sync.interval_minutes.max(1) * 60)
+            });
+        tokio::select! {
+            _ = crate::wait_shutdown() => break,
+            _ = tokio::time::sleep(interval) => {}
         }
-    }
-    for admin in remaining_admins {
-        if !admin_perms.is_empty() {
-            server
-                .add_user(
-                    admin,
-                    admin_perms.iter().map(|str| (*str).clone()).collect(),
-                )
-                .await?;
+
+        let Some(alert_only) = config::get()
+            .periodic_perms_sync
+            .as_ref()
+            .map(|periodic_sync| periodic_sync.alert_only)
+        else {
+            continue;
+        };
+        if let Err(err) = periodic_sync(&data, alert_only).await {
+            error!("Failed to run periodic perms sync: {}", err);
         }
     }
-    for user in remaining_panel_access {
-        if !panel_access_perms.is_empty() {
-            server
-                .add_user(
-                    user,
-                    panel_access_perms
-                        .iter()
-                        .map(|str| (*str).clone())
-                        .collect(),
-                )
-                .await?;
+}
+
+async fn periodic_sync(data: &ProtobotData, alert_only: bool) -> crate::Result<()> {
+    let sender = CommandSender::new("Periodic perms sync");
+    let mut plans = plan_all(data, &sender).await?;
+    plans.retain(|plan| !plan.changes.is_empty());
+    if plans.is_empty() {
+        return Ok(());
+    }
+
+    let mut alert = if alert_only {
+        "Panel subusers have drifted from the config. These changes would be made by a perms sync:\n"
+            .to_owned()
+    } else {
+        "Panel subusers had drifted from the config, and were synced:\n".to_owned()
+    };
+    for plan in &plans {
+        if !alert_only {
+            plan.apply(data).await?;
+        }
+        alert += &format!("**{}**\n```diff\n", plan.server.name);
+        for change in &plan.changes {
+            alert += &format!("{change}\n");
         }
+        alert += "```\n";
     }
+    warn!("{}", alert);
 
+    let Some(staff_channel) = config::get().special_channels.staff else {
+        return Ok(());
+    };
+    let message = if alert.len() > 2000 {
+        CreateMessage::new()
+            .content("Panel subusers have drifted from the config, see the attached report")
+            .add_file(CreateAttachment::bytes(alert, "perms_drift.md"))
+    } else {
+        CreateMessage::new().content(alert)
+    };
+    staff_channel
+        .send_message(&data.discord_handle, message)
+        .await?;
     Ok(())
 }
 
-async fn set_user_perms(
-    server: &Server<'_>,
-    user_uuid: Uuid,
-    perms: &HashSet<&String>,
+/// Plans perms changes for every proto server.
+async fn plan_all(data: &ProtobotData, sender: &CommandSender) -> crate::Result<Vec<ServerPlan>> {
+    let config = config::get();
+    let tiers = compute_tiers(data, sender).await?;
+    let mut plans = Vec::new();
+    for server in &config.pterodactyl_servers {
+        if server.category.is_proto() {
+            plans.push(plan_server(data, &tiers, server).await?);
+        }
+    }
+    Ok(plans)
+}
+
+/// Computes the emails of each panel access tier from the static config and the roles of the
+/// current guild members. Each email is only part of its highest tier.
+async fn compute_tiers(data: &ProtobotData, sender: &CommandSender) -> crate::Result<PanelTiers> {
+    let config = config::get();
+    let mut tiers = PanelTiers {
+        superadmin: config
+            .pterodactyl_emails
+            .superadmin
+            .iter()
+            .cloned()
+            .collect(),
+        admin: config.pterodactyl_emails.admin.iter().cloned().collect(),
+        normal: config.pterodactyl_emails.normal.iter().cloned().collect(),
+    };
+
+    let tier_roles = &config.pterodactyl_tier_roles;
+    if !tier_roles.superadmin.is_empty()
+        || !tier_roles.admin.is_empty()
+        || !tier_roles.normal.is_empty()
+    {
+        let panel_emails = GuildStorage::get(config.guild_id)
+            .await
+            .panel_emails
+            .clone();
+        let mut missing_emails = Vec::new();
+        let mut members = config.guild_id.members_iter(&data.discord_handle).boxed();
+        while let Some(member) = members.next().await {
+            let member = member?;
+            let has_any_role =
+                |roles: &[RoleId]| member.roles.iter().any(|role| roles.contains(role));
+            let tier = if has_any_role(&tier_roles.superadmin) {
+                &mut tiers.superadmin
+            } else if has_any_role(&tier_roles.admin) {
+                &mut tiers.admin
+            } else if has_any_role(&tier_roles.normal) {
+                &mut tiers.normal
+            } else {
+                continue;
+            };
+            match panel_emails.get(&member.user.id) {
+                Some(email) => {
+                    tier.insert(email.clone());
+                }
+                None => missing_emails.push(member.user.name.clone()),
+            }
+        }
+        if !missing_emails.is_empty() {
+            sender.info(format!(
+                "Members with a panel access role but no registered email (use /panel_email): {}",
+                missing_emails.join(", ")
+            ));
+        }
+    }
+
+    tiers
+        .admin
+        .retain(|email| !tiers.superadmin.contains(email));
+    tiers
+        .normal
+        .retain(|email| !tiers.superadmin.contains(email) && !tiers.admin.contains(email));
+    Ok(tiers)
+}
+
+/// Computes the changes needed to make the subusers of a server match the panel access tiers,
+/// without applying anything.
+async fn plan_server(
+    data: &ProtobotData,
+    tiers: &PanelTiers,
+    server: &PterodactylServer,
+) -> crate::Result<ServerPlan> {
+    let config = config::get();
+
+    let mut remaining: BTreeMap<_, _> = [
+        (&tiers.superadmin, PanelTier::Superadmin),
+        (&tiers.admin, PanelTier::Admin),
+        (&tiers.normal, PanelTier::Normal),
+    ]
+    .into_iter()
+    .flat_map(|(emails, tier)| emails.iter().map(move |email| (email, tier)))
+    .collect();
+    let ignored_emails: HashSet<_> = config.pterodactyl_emails.ignore.iter().collect();
+
+    let mut changes = Vec::new();
+
+    let existing_users = data.pterodactyl.get_server(server)?.list_users().await?;
+    for existing_user in existing_users {
+        let Some(tier) = remaining.remove(&existing_user.email) else {
+            if !ignored_emails.contains(&existing_user.email) {
+                changes.push(PermsChange::Remove {
+                    email: existing_user.email,
+                    uuid: existing_user.uuid,
+                });
+            }
+            continue;
+        };
+
+        let wanted_perms =
+            config
+                .pterodactyl_perms
+                .effective_perms(tier, server, &existing_user.email);
+        let existing_perms: BTreeSet<_> = existing_user.permissions.into_iter().collect();
+        if existing_perms == wanted_perms {
+            continue;
+        }
+        if wanted_perms.is_empty() {
+            changes.push(PermsChange::Remove {
+                email: existing_user.email,
+                uuid: existing_user.uuid,
+            });
+        } else {
+            changes.push(PermsChange::Edit {
+                email: existing_user.email,
+                uuid: existing_user.uuid,
+                added: wanted_perms.difference(&existing_perms).cloned().collect(),
+                removed: existing_perms.difference(&wanted_perms).cloned().collect(),
+                perms: wanted_perms.into_iter().collect(),
+            });
+        }
+    }
+
+    for (email, tier) in remaining {
+        let perms = config
+            .pterodactyl_perms
+            .effective_perms(tier, server, email);
+        if !perms.is_empty() {
+            changes.push(PermsChange::Add {
+                email: email.clone(),
+                perms: perms.into_iter().collect(),
+            });
+        }
+    }
+
+    Ok(ServerPlan {
+        server: server.clone(),
+        changes,
+    })
+}
+
+/// Shows the permissions a user would get on a server, and which layer they come from.
+async fn show_effective_perms(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    email: &str,
+    server_name: &str,
 ) -> crate::Result<()> {
-    if perms.is_empty() {
-        server.delete_user(user_uuid).await?;
+    let config = config::get();
+    let Some(server) = config
+        .pterodactyl_servers
+        .iter()
+        .find(|server| server.name == server_name)
+    else {
+        sender.error(format!("Unknown server: {}", server_name));
+        return Ok(());
+    };
+
+    let tiers = compute_tiers(data, &CommandSender::new(sender.name())).await?;
+    let Some(tier) = tiers.tier_of(email) else {
+        if config
+            .pterodactyl_emails
+            .ignore
+            .iter()
+            .any(|ignored| ignored.eq_ignore_ascii_case(email))
+        {
+            sender.info(format!("{email} is ignored by perms sync"));
+        } else {
+            sender.info(format!(
+                "{email} has no panel access tier, so has no access to {}",
+                server.name
+            ));
+        }
+        return Ok(());
+    };
+
+    let tier_perms = config.pterodactyl_perms.tier(tier);
+    let source = if tier_perms.server_overrides.contains_key(&server.name) {
+        format!("server override for {}", server.name)
+    } else if tier_perms.overrides.contains_key(&server.category) {
+        format!("category override for {:?}", server.category)
     } else {
-        server
-            .set_user_permissions(user_uuid, perms.iter().map(|str| (*str).clone()).collect())
-            .await?;
+        "tier default".to_owned()
+    };
+    sender.info(format!(
+        "{email} is {tier:?}, base perms from {source}: [{}]",
+        tier_perms.get_perms(server).join(", ")
+    ));
+    for user_override in config.pterodactyl_perms.user_overrides(email, server) {
+        sender.info(format!(
+            "User override: [{}]",
+            user_override
+                .add
+                .iter()
+                .map(|perm| format!("+{perm}"))
+                .chain(user_override.remove.iter().map(|perm| format!("-{perm}")))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
     }
+    let perms = config
+        .pterodactyl_perms
+        .effective_perms(tier, server, email);
+    sender.info(format!(
+        "Effective perms on {}: [{}]",
+        server.name,
+        perms.into_iter().collect::<Vec<_>>().join(", ")
+    ));
     Ok(())
 }
+
+fn print_usage(sender: &CommandSender) {
+    sender
+        .info("(perms_sync <server|all> [--dry-run]) | (perms_sync confirm) | (perms_sync cancel) | (perms_sync effective <email> <server>)");
+}
+
+struct PanelTiers {
+    superadmin: HashSet<String>,
+    admin: HashSet<String>,
+    normal: HashSet<String>,
+}
+
+impl PanelTiers {
+    fn tier_of(&self, email: &str) -> Option<PanelTier> {
+        [
+            (&self.superadmin, PanelTier::Superadmin),
+            (&self.admin, PanelTier::Admin),
+            (&self.normal, PanelTier::Normal),
+        ]
+        .into_iter()
+        .find(|(emails, _)| {
+            emails
+                .iter()
+                .any(|tier_email| tier_email.eq_ignore_ascii_case(email))
+        })
+        .map(|(_, tier)| tier)
+    }
+}
+
+struct PendingPlan {
+    plans: Vec<ServerPlan>,
+    created: Instant,
+}
+
+struct ServerPlan {
+    server: PterodactylServer,
+    changes: Vec<PermsChange>,
+}
+
+impl ServerPlan {
+    fn print(&self, sender: &CommandSender) {
+        sender.info(format!("Changes on {}:", self.server.name));
+        for change in &self.changes {
+            sender.info(format!("  {change}"));
+        }
+    }
+
+    async fn apply(&self, data: &ProtobotData) -> crate::Result<()> {
+        let server = data.pterodactyl.get_server(&self.server)?;
+        for change in &self.changes {
+            match change {
+                PermsChange::Add { email, perms } => {
+                    server.add_user(email, perms.clone()).await?;
+                }
+                PermsChange::Remove { uuid, .. } => {
+                    server.delete_user(*uuid).await?;
+                }
+                PermsChange::Edit { uuid, perms, .. } => {
+                    server.set_user_permissions(*uuid, perms.clone()).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for PermsChange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PermsChange::Add { email, perms } => write!(f, "+ {email} [{}]", perms.join(", ")),
+            PermsChange::Remove { email, .. } => write!(f, "- {email}"),
+            PermsChange::Edit {
+                email,
+                added,
+                removed,
+                ..
+            } => write!(
+                f,
+                "~ {email} [{}]",
+                added
+                    .iter()
+                    .map(|perm| format!("+{perm}"))
+                    .chain(removed.iter().map(|perm| format!("-{perm}")))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+enum PermsChange {
+    Add {
+        email: String,
+        perms: Vec<String>,
+    },
+    Remove {
+        email: String,
+        uuid: Uuid,
+    },
+    Edit {
+        email: String,
+        uuid: Uuid,
+        added: Vec<String>,
+        removed: Vec<String>,
+        perms: Vec<String>,
+    },
+}
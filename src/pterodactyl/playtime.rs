@@ -0,0 +1,245 @@
+use crate::command_sender::CommandSender;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::whitelist::whitelisted_players;
+use crate::pterodactyl::{parse_managed_category, PterodactylServerCategory};
+use crate::{config, ProtobotData};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// How often the last seen time of online players is updated, which bounds how much playtime is
+/// overcounted when the bot misses a leave message
+const SESSION_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+pub(crate) async fn run(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    match args.next() {
+        Some("inactive") => {
+            let (Some(category), Some(days)) = (args.next(), args.next()) else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = parse_managed_category(sender, category) else {
+                return Ok(());
+            };
+            let Some(days) = days.parse::<i64>().ok().filter(|days| *days >= 0) else {
+                sender.error(format!("Invalid number of days {days}"));
+                return Ok(());
+            };
+            inactive_report(data, sender, category, days).await?;
+        }
+        _ => print_usage(sender),
+    }
+    Ok(())
+}
+
+/// Lists whitelisted players who haven't joined any server of the category for the given number
+/// of days, least recently seen first.
+async fn inactive_report(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+    days: i64,
+) -> crate::Result<()> {
+    let Some(cutoff) =
+        chrono::Duration::try_days(days).and_then(|days| Utc::now().checked_sub_signed(days))
+    else {
+        sender.error(format!("Invalid number of days {days}"));
+        return Ok(());
+    };
+    let Some(whitelist) = whitelisted_players(data, sender, category).await? else {
+        return Ok(());
+    };
+    let config = config::get();
+
+    let mut inactive: Vec<_> = {
+        let storage = GuildStorage::get(config.guild_id).await;
+        whitelist
+            .into_iter()
+            .filter_map(|player| {
+                let key = player.to_ascii_lowercase();
+                let activities: Vec<_> = config
+                    .pterodactyl_servers(category)
+                    .filter_map(|server| storage.player_activity.get(&server.name)?.get(&key))
+                    .collect();
+                if activities.iter().any(|activity| activity.is_online()) {
+                    return None;
+                }
+                let last_seen = activities.iter().map(|activity| activity.last_seen()).max();
+                match last_seen {
+                    Some(last_seen) if last_seen >= cutoff => None,
+                    _ => Some((player, last_seen)),
+                }
+            })
+            .collect()
+    };
+    inactive.sort_by_key(|(_, last_seen)| *last_seen);
+
+    if inactive.is_empty() {
+        sender.info(format!(
+            "All whitelisted players on {category:?} have joined in the last {days} days"
+        ));
+        return Ok(());
+    }
+    sender.info(format!(
+        "{} whitelisted players on {:?} haven't joined in the last {} days:",
+        inactive.len(),
+        category,
+        days
+    ));
+    for (player, last_seen) in inactive {
+        match last_seen {
+            Some(last_seen) => sender.info(format!(
+                "  {} (last seen {})",
+                player,
+                last_seen.format("%Y-%m-%d")
+            )),
+            None => sender.info(format!("  {player} (never seen since tracking began)")),
+        }
+    }
+    Ok(())
+}
+
+pub(crate) async fn player_joined(server_name: &str, player: &str) {
+    let now = Utc::now().timestamp();
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let activity = storage
+        .player_activity
+        .entry(server_name.to_owned())
+        .or_default()
+        .entry(player.to_ascii_lowercase())
+        .or_default();
+    // A leave message was missed, so end that session when the player was last known to be online
+    activity.end_session(activity.last_seen);
+    activity.name = player.to_owned();
+    activity.session_start = Some(now);
+    activity.sessions += 1;
+    activity.last_seen = now;
+    storage.save().await;
+}
+
+pub(crate) async fn player_left(server_name: &str, player: &str) {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    match storage
+        .player_activity
+        .get_mut(server_name)
+        .and_then(|activities| activities.get_mut(&player.to_ascii_lowercase()))
+    {
+        Some(activity) => {
+            activity.end_session(Utc::now().timestamp());
+            storage.save().await;
+        }
+        None => storage.discard(),
+    }
+}
+
+/// Ends the open sessions on a server. If the server was seen stopping, sessions end now, otherwise
+/// they end when the players were last known to be online.
+pub(crate) async fn end_sessions(server_name: &str, stopped_now: bool) {
+    let now = Utc::now().timestamp();
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let Some(activities) = storage.player_activity.get_mut(server_name) else {
+        storage.discard();
+        return;
+    };
+    let mut changed = false;
+    for activity in activities.values_mut() {
+        if activity.session_start.is_some() {
+            activity.end_session(if stopped_now { now } else { activity.last_seen });
+            changed = true;
+        }
+    }
+    if changed {
+        storage.save().await;
+    } else {
+        storage.discard();
+    }
+}
+
+/// Periodically records that players in open sessions are still online.
+pub(crate) async fn run_session_heartbeat() {
+    loop {
+        tokio::select! {
+            _ = crate::wait_shutdown() => break,
+            _ = tokio::time::sleep(SESSION_HEARTBEAT_INTERVAL) => {}
+        }
+        let now = Utc::now().timestamp();
+        let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+        let mut changed = false;
+        for activity in storage
+            .player_activity
+            .values_mut()
+            .flat_map(HashMap::values_mut)
+        {
+            if activity.session_start.is_some() {
+                activity.last_seen = now;
+                changed = true;
+            }
+        }
+        if changed {
+            storage.save().await;
+        } else {
+            storage.discard();
+        }
+    }
+}
+
+/// Formats a playtime such as `3d 4h 5m`.
+pub(crate) fn format_playtime(seconds: i64) -> String {
+    let minutes = seconds / 60;
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+    if days > 0 {
+        format!("{days}d {hours}h {minutes}m")
+    } else if hours > 0 {
+        format!("{hours}h {minutes}m")
+    } else {
+        format!("{minutes}m")
+    }
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(playtime inactive <category> <days>)");
+}
+
+/// The playtime of a player on one server.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct PlayerActivity {
+    /// The player's name, as capitalized when they last joined
+    pub name: String,
+    /// The playtime of finished sessions
+    pub total_seconds: i64,
+    pub sessions: u32,
+    pub last_seen: i64,
+    /// When the current session started, if the player is online
+    #[serde(default)]
+    pub session_start: Option<i64>,
+}
+
+impl PlayerActivity {
+    fn end_session(&mut self, end: i64) {
+        if let Some(start) = self.session_start.take() {
+            self.total_seconds += (end - start).max(0);
+            self.last_seen = end;
+        }
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.session_start.is_some()
+    }
+
+    /// The playtime including the current session.
+    pub fn playtime_seconds(&self, now: DateTime<Utc>) -> i64 {
+        self.total_seconds
+            + self
+                .session_start
+                .map_or(0, |start| (now.timestamp() - start).max(0))
+    }
+
+    pub fn last_seen(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.last_seen, 0).unwrap_or_default()
+    }
+}
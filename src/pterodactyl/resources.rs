@@ -0,0 +1,334 @@
+use crate::config::{self, ResourceMonitoring};
+use crate::pterodactyl::PterodactylServer;
+use crate::ProtobotData;
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use log::{error, warn};
+use serenity::builder::CreateMessage;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// Server limits rarely change, so they are only refetched occasionally
+const LIMITS_REFRESH_INTERVAL: chrono::Duration = chrono::Duration::hours(1);
+
+fn histories() -> &'static Mutex<HashMap<String, ServerHistory>> {
+    static HISTORIES: OnceLock<Mutex<HashMap<String, ServerHistory>>> = OnceLock::new();
+    HISTORIES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Returns the resource usage samples of a server since the given time, along with its limits.
+pub(crate) fn history(
+    server_name: &str,
+    since: DateTime<Utc>,
+) -> Option<(Vec<ResourceSample>, ResourceLimits)> {
+    let histories = histories().lock().unwrap();
+    let history = histories.get(server_name)?;
+    let samples = history
+        .samples
+        .iter()
+        .filter(|sample| sample.time >= since)
+        .copied()
+        .collect();
+    Some((samples, history.limits))
+}
+
+/// Periodically polls the resource usage of every configured server, and alerts staff when usage
+/// stays above the configured thresholds.
+pub(crate) async fn run_monitor(data: ProtobotData) {
+    loop {
+        if let Err(err) = poll(&data).await {
+            error!("Failed to poll server resources: {}", err);
+        }
+        let interval = Duration::from_secs(
+            config::get()
+                .resource_monitoring
+                .poll_interval_seconds
+                .max(5),
+        );
+        tokio::select! {
+            _ = crate::wait_shutdown() => break,
+            _ = tokio::time::sleep(interval) => {}
+        }
+    }
+}
+
+async fn poll(data: &ProtobotData) -> crate::Result<()> {
+    let config = config::get();
+    let now = Utc::now();
+    let needs_limits: HashSet<String> = {
+        let histories = histories().lock().unwrap();
+        config
+            .pterodactyl_servers
+            .iter()
+            .filter(|server| {
+                histories
+                    .get(&server.name)
+                    .and_then(|history| history.limits_fetched_at)
+                    .is_none_or(|fetched_at| fetched_at + LIMITS_REFRESH_INTERVAL <= now)
+            })
+            .map(|server| server.name.clone())
+            .collect()
+    };
+
+    let results = join_all(config.pterodactyl_servers.iter().map(|server| {
+        let needs_limits = needs_limits.contains(&server.name);
+        async move { (server, poll_server(data, server, needs_limits).await) }
+    }))
+    .await;
+
+    let monitoring = &config.resource_monitoring;
+    // Out of range values are rejected when the config is loaded, but keep everything if one slips
+    // through rather than panicking
+    let retention_cutoff = i64::try_from(monitoring.history_hours)
+        .ok()
+        .and_then(chrono::Duration::try_hours)
+        .and_then(|retention| now.checked_sub_signed(retention));
+    let mut alerts = Vec::new();
+    {
+        let mut histories = histories().lock().unwrap();
+        histories.retain(|name, _| {
+            config
+                .pterodactyl_servers
+                .iter()
+                .any(|server| server.name == *name)
+        });
+        for (server, result) in results {
+            let history = histories.entry(server.name.clone()).or_default();
+            match result {
+                Ok((sample, limits)) => {
+                    if let Some(limits) = limits {
+                        history.limits = limits;
+                        history.limits_fetched_at = Some(now);
+                    }
+                    history.poll_failed = false;
+                    history.samples.push_back(sample);
+                    while history.samples.front().is_some_and(|sample| {
+                        retention_cutoff.is_some_and(|cutoff| sample.time < cutoff)
+                    }) {
+                        history.samples.pop_front();
+                    }
+                    alerts.extend(history.check_alerts(server, monitoring));
+                }
+                Err(err) => {
+                    // Only warn once per outage rather than on every poll
+                    if !history.poll_failed {
+                        warn!("Failed to poll resources of {}: {}", server.name, err);
+                    }
+                    history.poll_failed = true;
+                }
+            }
+        }
+    }
+
+    if alerts.is_empty() {
+        return Ok(());
+    }
+    for alert in &alerts {
+        warn!("{}", alert);
+    }
+    if let Some(staff_channel) = config.special_channels.staff {
+        staff_channel
+            .send_message(
+                &data.discord_handle,
+                CreateMessage::new().content(alerts.join("\n")),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+async fn poll_server(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    needs_limits: bool,
+) -> crate::Result<(ResourceSample, Option<ResourceLimits>)> {
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let limits = if needs_limits {
+        let limits = ptero_server.get_details().await?.limits;
+        let mebibytes = |amount: u64| (amount != 0).then_some(amount * 1024 * 1024);
+        Some(ResourceLimits {
+            cpu_percent: (limits.cpu != 0.0).then_some(limits.cpu as f64),
+            memory_bytes: mebibytes(limits.memory),
+            disk_bytes: mebibytes(limits.disk),
+        })
+    } else {
+        None
+    };
+    let resources = ptero_server.get_resources().await?.resources;
+    let sample = ResourceSample {
+        time: Utc::now(),
+        cpu_percent: resources.cpu_absolute as f64,
+        memory_bytes: resources.memory_bytes,
+        disk_bytes: resources.disk_bytes,
+        network_rx_bytes: resources.network_rx_bytes,
+        network_tx_bytes: resources.network_tx_bytes,
+    };
+    Ok((sample, limits))
+}
+
+#[derive(Default)]
+struct ServerHistory {
+    samples: VecDeque<ResourceSample>,
+    limits: ResourceLimits,
+    limits_fetched_at: Option<DateTime<Utc>>,
+    poll_failed: bool,
+    /// The metrics which are currently above their threshold and have been alerted about
+    alerting: HashSet<ResourceMetric>,
+}
+
+impl ServerHistory {
+    fn check_alerts(
+        &mut self,
+        server: &PterodactylServer,
+        monitoring: &ResourceMonitoring,
+    ) -> Vec<String> {
+        let Some(latest) = self.samples.back().copied() else {
+            return Vec::new();
+        };
+        let Some(window_start) = i64::try_from(monitoring.alert_after_minutes)
+            .ok()
+            .and_then(chrono::Duration::try_minutes)
+            .and_then(|window| latest.time.checked_sub_signed(window))
+        else {
+            return Vec::new();
+        };
+
+        let mut alerts = Vec::new();
+        for metric in ResourceMetric::ALL {
+            let (Some(threshold), Some(limit)) =
+                (metric.alert_percent(monitoring), metric.limit(&self.limits))
+            else {
+                self.alerting.remove(&metric);
+                continue;
+            };
+            let percent = |sample: &ResourceSample| metric.value(sample) / limit * 100.0;
+
+            if self.alerting.contains(&metric) {
+                if percent(&latest) < threshold {
+                    self.alerting.remove(&metric);
+                    alerts.push(format!(
+                        "**{}** {} usage is back below {}% of its limit ({})",
+                        server.display_name,
+                        metric.name(),
+                        threshold,
+                        metric.format(metric.value(&latest)),
+                    ));
+                }
+            } else {
+                let window_covered = self
+                    .samples
+                    .front()
+                    .is_some_and(|sample| sample.time <= window_start);
+                let sustained = window_covered
+                    && self
+                        .samples
+                        .iter()
+                        .filter(|sample| sample.time >= window_start)
+                        .all(|sample| percent(sample) >= threshold);
+                if sustained {
+                    self.alerting.insert(metric);
+                    alerts.push(format!(
+                        "**{}** {} usage has been above {}% of its limit for {} minutes (currently {} of {})",
+                        server.display_name,
+                        metric.name(),
+                        threshold,
+                        monitoring.alert_after_minutes,
+                        metric.format(metric.value(&latest)),
+                        metric.format(limit),
+                    ));
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ResourceSample {
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) cpu_percent: f64,
+    pub(crate) memory_bytes: u64,
+    pub(crate) disk_bytes: u64,
+    /// Total bytes received since the server started
+    pub(crate) network_rx_bytes: u64,
+    /// Total bytes sent since the server started
+    pub(crate) network_tx_bytes: u64,
+}
+
+/// The limits of a server, or `None` where unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ResourceLimits {
+    pub(crate) cpu_percent: Option<f64>,
+    pub(crate) memory_bytes: Option<u64>,
+    pub(crate) disk_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum ResourceMetric {
+    Cpu,
+    Memory,
+    Disk,
+}
+
+impl ResourceMetric {
+    pub(crate) const ALL: [ResourceMetric; 3] = [
+        ResourceMetric::Cpu,
+        ResourceMetric::Memory,
+        ResourceMetric::Disk,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            ResourceMetric::Cpu => "CPU",
+            ResourceMetric::Memory => "Memory",
+            ResourceMetric::Disk => "Disk",
+        }
+    }
+
+    pub(crate) fn value(self, sample: &ResourceSample) -> f64 {
+        match self {
+            ResourceMetric::Cpu => sample.cpu_percent,
+            ResourceMetric::Memory => sample.memory_bytes as f64,
+            ResourceMetric::Disk => sample.disk_bytes as f64,
+        }
+    }
+
+    pub(crate) fn limit(self, limits: &ResourceLimits) -> Option<f64> {
+        match self {
+            ResourceMetric::Cpu => limits.cpu_percent,
+            ResourceMetric::Memory => limits.memory_bytes.map(|bytes| bytes as f64),
+            ResourceMetric::Disk => limits.disk_bytes.map(|bytes| bytes as f64),
+        }
+    }
+
+    fn alert_percent(self, monitoring: &ResourceMonitoring) -> Option<f64> {
+        match self {
+            ResourceMetric::Cpu => monitoring.cpu_alert_percent,
+            ResourceMetric::Memory => monitoring.memory_alert_percent,
+            ResourceMetric::Disk => monitoring.disk_alert_percent,
+        }
+    }
+
+    pub(crate) fn format(self, value: f64) -> String {
+        match self {
+            ResourceMetric::Cpu => format!("{value:.0}%"),
+            ResourceMetric::Memory | ResourceMetric::Disk => format_bytes(value),
+        }
+    }
+}
+
+pub(crate) fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{value:.0} {}", UNITS[unit])
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,372 @@
+use crate::command_sender::CommandSender;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::whitelist::{remove_expiry, set_expiry, whitelist_add, whitelist_remove};
+use crate::pterodactyl::{lookup_player, PterodactylServerCategory};
+use crate::{config, ProtobotData};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serenity::http::{HttpError, StatusCode};
+use serenity::model::id::{RoleId, UserId};
+use std::collections::BTreeSet;
+use std::sync::OnceLock;
+use tokio::sync::{Mutex, OnceCell};
+use uuid::Uuid;
+
+/// Prevents concurrent syncs from granting the same whitelist entry twice
+fn sync_mutex() -> &'static Mutex<()> {
+    static SYNC_MUTEX: OnceLock<Mutex<()>> = OnceLock::new();
+    SYNC_MUTEX.get_or_init(|| Mutex::new(()))
+}
+
+pub(crate) async fn run(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    mut args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
+    let Some(operation) = args.next() else {
+        print_usage(sender);
+        return Ok(());
+    };
+    match operation {
+        "list" => {
+            let storage = GuildStorage::get(config::get().guild_id).await;
+            if storage.linked_accounts.is_empty() {
+                sender.info("There are no linked accounts");
+            }
+            for (user_id, account) in &storage.linked_accounts {
+                let categories: Vec<_> = storage
+                    .role_whitelist_grants
+                    .iter()
+                    .filter(|grant| grant.user == *user_id)
+                    .map(|grant| {
+                        if grant.pending_removal {
+                            format!("{:?} (pending removal)", grant.category)
+                        } else {
+                            format!("{:?}", grant.category)
+                        }
+                    })
+                    .collect();
+                sender.info(format!(
+                    "{}{} (ID {}): whitelisted by role on [{}]",
+                    account.name,
+                    if account.verified {
+                        ""
+                    } else {
+                        " (unverified)"
+                    },
+                    user_id,
+                    categories.join(", ")
+                ));
+            }
+        }
+        "sync" => {
+            let config = config::get();
+            let user_ids: Vec<_> = GuildStorage::get(config.guild_id)
+                .await
+                .linked_accounts
+                .keys()
+                .copied()
+                .collect();
+            for user_id in user_ids {
+                let roles = member_roles(data, user_id).await?;
+                sync_member(data, sender, user_id, &roles).await?;
+            }
+            sender.info("Synced role whitelists");
+        }
+        _ => print_usage(sender),
+    }
+    Ok(())
+}
+
+/// Links a Discord user to a Minecraft account, which is whitelisted according to the user's roles
+/// once it is verified. Returns the correctly capitalized name of the linked account, and the code
+/// to verify it with in game.
+pub(crate) async fn link_account(
+    data: &ProtobotData,
+    user_id: UserId,
+    player: &str,
+) -> crate::Result<Result<(String, String), String>> {
+    let (name, uuid) = lookup_player(player).await?;
+    let guild_id = config::get().guild_id;
+
+    let (previous_account, other_claims) = {
+        let storage = GuildStorage::get(guild_id).await;
+        let mut other_claims = Vec::new();
+        for (&other_user, account) in &storage.linked_accounts {
+            if other_user == user_id || account.uuid != uuid {
+                continue;
+            }
+            if account.verified {
+                return Ok(Err(format!(
+                    "{name} is already linked to another Discord account"
+                )));
+            }
+            other_claims.push(other_user);
+        }
+        (storage.linked_accounts.get(&user_id).cloned(), other_claims)
+    };
+
+    let sender = role_whitelist_sender();
+    if let Some(previous_account) = previous_account {
+        if previous_account.uuid == uuid {
+            return Ok(Err(format!("You are already linked to {name}")));
+        }
+        sync_member(data, &sender, user_id, &[]).await?;
+    }
+    // Unverified links don't prove ownership, so they can't keep the real owner from linking
+    for other_user in other_claims {
+        sync_member(data, &sender, other_user, &[]).await?;
+        let mut storage = GuildStorage::get_mut(guild_id).await;
+        storage.linked_accounts.remove(&other_user);
+        storage.save().await;
+    }
+
+    let verification_code = format!("{:06}", rand::random_range(0..1_000_000));
+    let mut storage = GuildStorage::get_mut(guild_id).await;
+    storage.linked_accounts.insert(
+        user_id,
+        LinkedAccount {
+            name: name.clone(),
+            uuid,
+            verified: false,
+            verification_code: Some(verification_code.clone()),
+        },
+    );
+    storage.save().await;
+    Ok(Ok((name, verification_code)))
+}
+
+/// Verifies the linked account of a player who typed their verification code in game, and
+/// whitelists it according to the user's roles. Returns whether the code matched.
+pub(crate) async fn verify_account(
+    data: &ProtobotData,
+    player_name: &str,
+    code: &str,
+) -> crate::Result<bool> {
+    let user_id = {
+        let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+        let Some((&user_id, account)) = storage.linked_accounts.iter_mut().find(|(_, account)| {
+            account.name.eq_ignore_ascii_case(player_name)
+                && account.verification_code.as_deref() == Some(code)
+        }) else {
+            storage.discard();
+            return Ok(false);
+        };
+        account.verified = true;
+        account.verification_code = None;
+        storage.save().await;
+        user_id
+    };
+
+    let roles = member_roles(data, user_id).await?;
+    sync_member(data, &role_whitelist_sender(), user_id, &roles).await?;
+    Ok(true)
+}
+
+/// The roles of a member, or none if they left the guild.
+async fn member_roles(data: &ProtobotData, user_id: UserId) -> crate::Result<Vec<RoleId>> {
+    match config::get()
+        .guild_id
+        .member(&data.discord_handle, user_id)
+        .await
+    {
+        Ok(member) => Ok(member.roles),
+        Err(serenity::Error::Http(HttpError::UnsuccessfulRequest(response)))
+            if response.status_code == StatusCode::NOT_FOUND =>
+        {
+            Ok(Vec::new())
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Unlinks a Discord user's Minecraft account, removing any whitelist entries granted by roles.
+/// Returns the name of the previously linked account.
+pub(crate) async fn unlink_account(
+    data: &ProtobotData,
+    user_id: UserId,
+) -> crate::Result<Option<String>> {
+    let guild_id = config::get().guild_id;
+    let Some(account) = GuildStorage::get(guild_id)
+        .await
+        .linked_accounts
+        .get(&user_id)
+        .cloned()
+    else {
+        return Ok(None);
+    };
+
+    sync_member(data, &role_whitelist_sender(), user_id, &[]).await?;
+
+    let mut storage = GuildStorage::get_mut(guild_id).await;
+    storage.linked_accounts.remove(&user_id);
+    storage.save().await;
+    Ok(Some(account.name))
+}
+
+/// Makes the role-granted whitelist entries of a user's linked account match their roles. Entries
+/// for roles that were lost are removed after the configured grace period, using the whitelist
+/// expiry mechanism. Unverified accounts get no entries, as anyone can link any account.
+pub(crate) async fn sync_member(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    user_id: UserId,
+    roles: &[RoleId],
+) -> crate::Result<()> {
+    let _guard = sync_mutex().lock().await;
+    let config = config::get();
+    let (account, grants) = {
+        let storage = GuildStorage::get(config.guild_id).await;
+        let Some(account) = storage.linked_accounts.get(&user_id).cloned() else {
+            return Ok(());
+        };
+        let grants: Vec<_> = storage
+            .role_whitelist_grants
+            .iter()
+            .filter(|grant| {
+                grant.user == user_id && grant.player.eq_ignore_ascii_case(&account.name)
+            })
+            .cloned()
+            .collect();
+        (account, grants)
+    };
+
+    let roles = if account.verified { roles } else { &[] };
+    let categories: BTreeSet<PterodactylServerCategory> = roles
+        .iter()
+        .filter_map(|role| config.role_whitelists.roles.get(role))
+        .flatten()
+        .copied()
+        .collect();
+
+    for &category in &categories {
+        match grants.iter().find(|grant| grant.category == category) {
+            Some(grant) if grant.pending_removal => {
+                remove_expiry(&account.name, category).await;
+                update_grant(user_id, category, |grant| grant.pending_removal = false).await;
+                sender.info(format!(
+                    "Cancelled removal of {} from {:?}",
+                    account.name, category
+                ));
+            }
+            Some(_) => {}
+            None => {
+                let name_and_uuid = OnceCell::new_with(Some((account.name.clone(), account.uuid)));
+                let added =
+                    whitelist_add(data, sender, &account.name, category, &name_and_uuid).await?;
+                let mut storage = GuildStorage::get_mut(config.guild_id).await;
+                storage.role_whitelist_grants.push(RoleWhitelistGrant {
+                    user: user_id,
+                    player: account.name.clone(),
+                    category,
+                    added,
+                    pending_removal: false,
+                });
+                storage.save().await;
+            }
+        }
+    }
+
+    for grant in grants {
+        if categories.contains(&grant.category) || grant.pending_removal {
+            continue;
+        }
+        if !grant.added {
+            // The player was already whitelisted before they got the role, so leave them be
+            forget_grants(&grant.player, grant.category).await;
+        } else if config.role_whitelists.grace_period_hours == 0 {
+            whitelist_remove(data, sender, &grant.player, grant.category).await?;
+        } else {
+            let Some(expires_at) =
+                chrono::Duration::try_hours(config.role_whitelists.grace_period_hours)
+                    .and_then(|grace_period| Utc::now().checked_add_signed(grace_period))
+            else {
+                sender.error(format!(
+                    "Can't remove {} from {:?} after an invalid grace period",
+                    grant.player, grant.category
+                ));
+                continue;
+            };
+            set_expiry(&grant.player, grant.category, expires_at).await;
+            update_grant(user_id, grant.category, |grant| {
+                grant.pending_removal = true
+            })
+            .await;
+            sender.info(format!(
+                "{} will be removed from {:?} in {} hours",
+                grant.player, grant.category, config.role_whitelists.grace_period_hours
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Forgets the role-granted whitelist entries of a player, called when the player is removed from
+/// the whitelist.
+pub(crate) async fn forget_grants(player_name: &str, category: PterodactylServerCategory) {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let num_grants = storage.role_whitelist_grants.len();
+    storage.role_whitelist_grants.retain(|grant| {
+        grant.category != category || !grant.player.eq_ignore_ascii_case(player_name)
+    });
+    if storage.role_whitelist_grants.len() == num_grants {
+        storage.discard();
+    } else {
+        storage.save().await;
+    }
+}
+
+async fn update_grant(
+    user_id: UserId,
+    category: PterodactylServerCategory,
+    update: impl FnOnce(&mut RoleWhitelistGrant),
+) {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    match storage
+        .role_whitelist_grants
+        .iter_mut()
+        .find(|grant| grant.user == user_id && grant.category == category)
+    {
+        Some(grant) => {
+            update(grant);
+            storage.save().await;
+        }
+        None => storage.discard(),
+    }
+}
+
+pub(crate) fn role_whitelist_sender() -> CommandSender {
+    CommandSender::new("Role whitelist")
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(links list) | (links sync)");
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LinkedAccount {
+    pub name: String,
+    pub uuid: Uuid,
+    /// Whether the player proved they own the account by typing their code in game. Anyone can
+    /// link any account, so only verified accounts are whitelisted or trusted with staff
+    /// privileges.
+    #[serde(default)]
+    pub verified: bool,
+    /// The code to type in game with `!link` to verify the account
+    #[serde(default)]
+    pub verification_code: Option<String>,
+}
+
+/// A whitelist entry that was granted because of a Discord role.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RoleWhitelistGrant {
+    user: UserId,
+    player: String,
+    category: PterodactylServerCategory,
+    /// Whether the player was actually added to the whitelist, as opposed to already being on it
+    added: bool,
+    /// Whether the role was lost and the player is waiting for the grace period to end
+    #[serde(default)]
+    pending_removal: bool,
+}
@@ -1,4 +1,7 @@
-use crate::pterodactyl::{tellraw, PterodactylServer};
+use crate::pterodactyl::{
+    console_query, console_rules, game_commands, lag, maintenance, online_players, playtime,
+    tellraw, PterodactylPanels, PterodactylServer,
+};
 use crate::{config, discord_bot, ProtobotData};
 use dashmap::{DashMap, Entry};
 use futures::future::try_join_all;
@@ -57,71 +60,14 @@ pub(crate) async fn create_backup(
 async fn handle_chat_message(
     data: &ProtobotData,
     webhook_cache: &DashMap<String, Webhook>,
-    ptero_server_id: &str,
+    server: &PterodactylServer,
     ptero_server: &pterodactyl_api::client::Server<'_>,
     sender: &str,
     message: &str,
 ) -> crate::Result<()> {
-    let config = config::get();
-    let Some(server) = config
-        .pterodactyl_servers
-        .iter()
-        .find(|server| server.id == ptero_server_id)
-    else {
-        return Ok(());
-    };
-
     if let Some(command) = message.strip_prefix('!') {
         if server.allow_commands {
-            info!("Received command {} from {}", command, sender);
-            let args: Vec<_> = command.split_whitespace().collect();
-            match args[0] {
-                "s" => {
-                    if args.len() > 1 {
-                        ptero_server
-                            .send_command(&format!(
-                                "scoreboard objectives setdisplay sidebar {}",
-                                args[1]
-                            ))
-                            .await?;
-                    } else {
-                        ptero_server
-                            .send_command("scoreboard objectives setdisplay sidebar")
-                            .await?;
-                    }
-                }
-                "t" => {
-                    if args.len() > 1 {
-                        ptero_server
-                            .send_command(&format!(
-                                "scoreboard objectives setdisplay list {}",
-                                args[1]
-                            ))
-                            .await?;
-                    } else {
-                        ptero_server
-                            .send_command("scoreboard objectives setdisplay list")
-                            .await?;
-                    }
-                }
-                "backup" => {
-                    create_backup(
-                        ptero_server,
-                        if args.len() > 1 {
-                            Some(args[1..].join(" "))
-                        } else {
-                            None
-                        },
-                    )
-                    .await?;
-                    ptero_server
-                        .send_command(
-                            "tellraw @a \"Backup being created. Wait a minute to be sure the backup has finished\"",
-                        )
-                        .await?;
-                }
-                _ => {}
-            }
+            game_commands::handle_command(data, server, ptero_server, sender, command).await?;
             return Ok(());
         }
     }
@@ -130,7 +76,7 @@ async fn handle_chat_message(
         &data.discord_handle,
         &data.pterodactyl,
         webhook_cache,
-        ptero_server_id,
+        server,
         Some(sender),
         false,
         message.to_owned(),
@@ -143,7 +89,7 @@ async fn handle_chat_message(
 async fn handle_log_message(
     data: &ProtobotData,
     webhook_cache: &DashMap<String, Webhook>,
-    ptero_server_id: &str,
+    server: &PterodactylServer,
     message: &str,
 ) -> crate::Result<()> {
     #[allow(clippy::manual_map)]
@@ -156,12 +102,19 @@ async fn handle_log_message(
     };
     if let Some((username, action)) = leave_join_user_action {
         let sanitized_username = sanitize_username(username, true);
+        if action == "joined the game" {
+            online_players::player_joined(&server.name, &sanitized_username);
+            playtime::player_joined(&server.name, &sanitized_username).await;
+        } else {
+            online_players::player_left(&server.name, &sanitized_username);
+            playtime::player_left(&server.name, &sanitized_username).await;
+        }
         let message = format!("{} {}", sanitize_username(username, false), action);
         broadcast_message(
             &data.discord_handle,
             &data.pterodactyl,
             webhook_cache,
-            ptero_server_id,
+            server,
             Some(&sanitized_username),
             true,
             message,
@@ -175,10 +128,19 @@ async fn handle_log_message(
 async fn handle_server_log(
     data: &ProtobotData,
     webhook_cache: &DashMap<String, Webhook>,
-    ptero_server_id: &str,
+    server: &PterodactylServer,
     ptero_server: &pterodactyl_api::client::Server<'_>,
     message: &str,
 ) -> crate::Result<()> {
+    console_query::handle_console_line(server, message);
+    // Alerts and rule actions failing must not stop the line from being bridged
+    if let Err(err) = lag::handle_console_line(data, server, message).await {
+        warn!("Failed to handle lag on {}: {}", server.name, err);
+    }
+    if let Err(err) = console_rules::handle_console_line(data, server, message).await {
+        warn!("Failed to run console rules on {}: {}", server.name, err);
+    }
+
     let parse_result: Result<(&str, (&str, &str)), nom::error::Error<&str>> = map(
         (
             (
@@ -202,7 +164,7 @@ async fn handle_server_log(
         handle_chat_message(
             data,
             webhook_cache,
-            ptero_server_id,
+            server,
             ptero_server,
             &sanitize_username(sender, true),
             message,
@@ -229,30 +191,41 @@ async fn handle_server_log(
     .parse(message)
     .finish();
     if let Ok((_, log_message)) = parse_result {
-        handle_log_message(data, webhook_cache, ptero_server_id, log_message).await?;
+        handle_log_message(data, webhook_cache, server, log_message).await?;
     }
 
     Ok(())
 }
 
+/// Posts a system message from a server through its chat bridge, to the other bridged servers and
+/// Discord.
+pub(crate) async fn broadcast_system_message(
+    data: &ProtobotData,
+    server: &PterodactylServer,
+    message: String,
+) -> crate::Result<()> {
+    broadcast_message(
+        &data.discord_handle,
+        &data.pterodactyl,
+        &DashMap::new(),
+        server,
+        None,
+        true,
+        message,
+    )
+    .await
+}
+
 async fn broadcast_message(
     discord_handle: &discord_bot::Handle,
-    pterodactyl: &pterodactyl_api::client::Client,
+    pterodactyl: &PterodactylPanels,
     webhook_cache: &DashMap<String, Webhook>,
-    ptero_server_id: &str,
+    from_server: &PterodactylServer,
     username: Option<&str>,
     system_message: bool,
     message: String,
 ) -> crate::Result<()> {
     let config = config::get();
-    let Some(from_server) = config
-        .pterodactyl_servers
-        .iter()
-        .find(|server| server.id == ptero_server_id)
-    else {
-        warn!("Unknown server {}", ptero_server_id);
-        return Ok(());
-    };
     let Some(chat_bridge) = config.chat_bridge_by_ptero_server_name(&from_server.name) else {
         return Ok(());
     };
@@ -281,7 +254,7 @@ async fn broadcast_message(
                     return None;
                 };
                 Some(async {
-                    tellraw(&pterodactyl.get_server(&server.id), &pterodactyl_message).await
+                    tellraw(&pterodactyl.get_server(server)?, &pterodactyl_message).await
                 })
             }),
     )
@@ -404,7 +377,7 @@ fn sanitize_username(username: &str, remove_team_prefix: bool) -> Cow<'_, str> {
 
 struct WebsocketListener<'a> {
     data: ProtobotData,
-    ptero_server_id: &'a str,
+    server: &'a PterodactylServer,
     last_server_status: Option<ServerState>,
     webhook_cache: Arc<DashMap<String, Webhook>>,
 }
@@ -417,19 +390,16 @@ impl<H: PteroWebSocketHandle> PteroWebSocketListener<H> for WebsocketListener<'_
     ) -> pterodactyl_api::Result<()> {
         let output = output.to_owned();
         let data = self.data.clone();
-        let ptero_server_id = self.ptero_server_id.to_owned();
+        let server = self.server.clone();
         let webhook_cache = self.webhook_cache.clone();
         tokio::runtime::Handle::current().spawn(async move {
-            let ptero_server = data.pterodactyl.get_server(&ptero_server_id);
-            if let Err(err) = handle_server_log(
-                &data,
-                &webhook_cache,
-                &ptero_server_id,
-                &ptero_server,
-                &output,
-            )
-            .await
-            {
+            let result = match data.pterodactyl.get_server(&server) {
+                Ok(ptero_server) => {
+                    handle_server_log(&data, &webhook_cache, &server, &ptero_server, &output).await
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(err) = result {
                 error!("Error handling console output: {}", err);
             }
         });
@@ -443,6 +413,18 @@ impl<H: PteroWebSocketHandle> PteroWebSocketListener<H> for WebsocketListener<'_
     ) -> pterodactyl_api::Result<()> {
         let last_status = self.last_server_status;
         self.last_server_status = Some(status);
+        if matches!(status, ServerState::Offline | ServerState::Starting) {
+            online_players::server_restarted(&self.server.name);
+        }
+        // Sessions left open by a crash or while the bot was down end when the players were last
+        // known to be online
+        if matches!(status, ServerState::Offline | ServerState::Starting) || last_status.is_none() {
+            let server_name = self.server.name.clone();
+            let stopped_now = status == ServerState::Offline && last_status.is_some();
+            tokio::runtime::Handle::current().spawn(async move {
+                playtime::end_sessions(&server_name, stopped_now).await;
+            });
+        }
         if last_status.is_none_or(|last_status| last_status == status) {
             return Ok(());
         }
@@ -456,16 +438,21 @@ impl<H: PteroWebSocketHandle> PteroWebSocketListener<H> for WebsocketListener<'_
 
         let data = self.data.clone();
         let webhook_cache = self.webhook_cache.clone();
-        let ptero_server_id = self.ptero_server_id.to_owned();
+        let server = self.server.clone();
         tokio::runtime::Handle::current().spawn(async move {
+            let message = if maintenance::is_in_maintenance(&server.name).await {
+                format!("{message} (in maintenance)")
+            } else {
+                message.to_owned()
+            };
             if let Err(err) = broadcast_message(
                 &data.discord_handle,
                 &data.pterodactyl,
                 &webhook_cache,
-                &ptero_server_id,
+                &server,
                 None,
                 true,
-                message.to_owned(),
+                message,
             )
             .await
             {
@@ -480,11 +467,11 @@ pub(crate) async fn run(server: PterodactylServer, data: ProtobotData) -> crate:
     info!("Starting websocket for server {}", server.name);
     let listener = WebsocketListener {
         data: data.clone(),
-        ptero_server_id: &server.id,
+        server: &server,
         last_server_status: None,
         webhook_cache: Arc::new(DashMap::new()),
     };
-    let ptero_server = data.pterodactyl.get_server(&server.id);
+    let ptero_server = data.pterodactyl.get_server(&server)?;
     tokio::select! {
         _ = crate::wait_shutdown() => {}
         result = ptero_server.run_websocket_loop(|url| async {
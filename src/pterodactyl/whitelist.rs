@@ -1,177 +1,204 @@
-use crate::pterodactyl::{send_command_safe, PterodactylServerCategory};
+use crate::command_sender::CommandSender;
+use crate::discord_bot::guild_storage::GuildStorage;
+use crate::pterodactyl::{
+    lookup_player, maintenance, ops, parse_duration, parse_managed_category, role_whitelist,
+    run_across_categories, send_command_safe, PterodactylServerCategory,
+};
 use crate::{config, ProtobotData};
-use futures::future::try_join_all;
-use git_version::git_version;
-use log::{error, info};
-use serde::de::value::StrDeserializer;
+use chrono::{DateTime, Utc};
+use log::{error, warn};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
-use std::future::Future;
+use serenity::builder::CreateMessage;
+use std::time::Duration;
 use tokio::sync::OnceCell;
 use uuid::Uuid;
 
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const EXPIRY_REMINDER_TIME: chrono::TimeDelta = chrono::TimeDelta::days(1);
+
 pub(crate) async fn run(
     data: &ProtobotData,
+    sender: &CommandSender,
     mut args: impl Iterator<Item = &str>,
 ) -> crate::Result<()> {
     let Some(operation) = args.next() else {
-        print_usage();
+        print_usage(sender);
         return Ok(());
     };
     match operation {
         "add" => {
             let Some(player) = args.next() else {
-                print_usage();
+                print_usage(sender);
                 return Ok(());
             };
             let Some(category) = args.next() else {
-                print_usage();
+                print_usage(sender);
                 return Ok(());
             };
             let uuid = args.next().map(Uuid::parse_str).transpose()?;
-            whitelist_across_categories(category, |category| {
-                whitelist_add(
-                    data,
-                    player,
-                    category,
-                    uuid.map_or_else(OnceCell::new, |uuid| {
-                        OnceCell::new_with(Some((player.to_owned(), uuid)))
-                    }),
-                )
+            let name_and_uuid = uuid.map_or_else(OnceCell::new, |uuid| {
+                OnceCell::new_with(Some((player.to_owned(), uuid)))
+            });
+            run_across_categories(sender, category, |category| {
+                let name_and_uuid = &name_and_uuid;
+                async move {
+                    whitelist_add(data, sender, player, category, name_and_uuid).await?;
+                    Ok(())
+                }
             })
             .await?;
         }
-        "remove" => {
+        "temp" => {
             let Some(player) = args.next() else {
-                print_usage();
+                print_usage(sender);
                 return Ok(());
             };
             let Some(category) = args.next() else {
-                print_usage();
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(duration) = args.next().and_then(parse_duration) else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(expires_at) = Utc::now().checked_add_signed(duration) else {
+                sender.error("That duration is too long");
                 return Ok(());
             };
-            whitelist_across_categories(category, |category| {
-                whitelist_remove(data, player, category)
+            let name_and_uuid = OnceCell::new();
+            run_across_categories(sender, category, |category| {
+                let name_and_uuid = &name_and_uuid;
+                async move {
+                    if whitelist_add(data, sender, player, category, name_and_uuid).await? {
+                        set_expiry(player, category, expires_at).await;
+                        sender.info(format!(
+                            "{player} will be removed from {category:?} at {}",
+                            format_time(expires_at)
+                        ));
+                    }
+                    Ok(())
+                }
             })
             .await?;
         }
-        "list" => {
+        "extend" => {
+            let Some(player) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
             let Some(category) = args.next() else {
-                print_usage();
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(duration) = args.next().and_then(parse_duration) else {
+                print_usage(sender);
                 return Ok(());
             };
-            let Ok(category) = PterodactylServerCategory::deserialize(StrDeserializer::<
-                serde_json::Error,
-            >::new(category)) else {
-                error!("Unknown category {}", category);
+            run_across_categories(sender, category, |category| async move {
+                match extend_expiry(player, category, duration).await {
+                    Ok(expires_at) => sender.info(format!(
+                        "{player} will now be removed from {category:?} at {}",
+                        format_time(expires_at)
+                    )),
+                    Err(ExtendError::NotTemporary) => sender.error(format!(
+                        "{player} is not temporarily whitelisted on {category:?}"
+                    )),
+                    Err(ExtendError::TooLong) => sender.error("That duration is too long"),
+                }
+                Ok(())
+            })
+            .await?;
+        }
+        "permanent" => {
+            let Some(player) = args.next() else {
+                print_usage(sender);
                 return Ok(());
             };
-            if !category.is_proto() || !category.is_minecraft() {
-                error!("Can only whitelist on ProtoTech Minecraft servers");
+            let Some(category) = args.next() else {
+                print_usage(sender);
                 return Ok(());
+            };
+            run_across_categories(sender, category, |category| async move {
+                if remove_expiry(player, category).await {
+                    sender.info(format!(
+                        "{player} is now permanently whitelisted on {category:?}"
+                    ));
+                } else {
+                    sender.error(format!(
+                        "{player} is not temporarily whitelisted on {category:?}"
+                    ));
+                }
+                Ok(())
+            })
+            .await?;
+        }
+        "expiries" => {
+            let storage = GuildStorage::get(config::get().guild_id).await;
+            if storage.whitelist_expiries.is_empty() {
+                sender.info("There are no temporary whitelist entries");
+            }
+            for expiry in &storage.whitelist_expiries {
+                sender.info(format!(
+                    "{} on {:?}: expires at {}",
+                    expiry.player,
+                    expiry.category,
+                    format_time(expiry.expires_at())
+                ));
             }
-            whitelist_list(data, category).await?;
         }
-        _ => {
-            print_usage();
+        "remove" => {
+            let Some(player) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            run_across_categories(sender, category, |category| {
+                whitelist_remove(data, sender, player, category)
+            })
+            .await?;
         }
-    }
-    Ok(())
-}
-
-async fn whitelist_across_categories<F, Fut>(
-    category: &str,
-    mut whitelist_operation: F,
-) -> crate::Result<()>
-where
-    F: FnMut(PterodactylServerCategory) -> Fut,
-    Fut: Future<Output = crate::Result<()>>,
-{
-    if category == "all" {
-        let categories: BTreeSet<_> = config::get()
-            .pterodactyl_servers
-            .iter()
-            .map(|server| server.category)
-            .filter(|category| category.is_proto() && category.is_minecraft())
-            .collect();
-        try_join_all(categories.into_iter().map(whitelist_operation)).await?;
-    } else {
-        let Ok(category) = PterodactylServerCategory::deserialize(StrDeserializer::<
-            serde_json::Error,
-        >::new(category)) else {
-            error!("Unknown category {}", category);
-            return Ok(());
-        };
-        if !category.is_proto() || !category.is_minecraft() {
-            error!("Can only whitelist on ProtoTech Minecraft servers");
-            return Ok(());
+        "list" => {
+            let Some(category) = args.next() else {
+                print_usage(sender);
+                return Ok(());
+            };
+            let Some(category) = parse_managed_category(sender, category) else {
+                return Ok(());
+            };
+            whitelist_list(data, sender, category).await?;
+        }
+        _ => {
+            print_usage(sender);
         }
-        whitelist_operation(category).await?;
     }
     Ok(())
 }
 
-async fn whitelist_add(
+/// Whitelists a player on every server in a category, returning whether they were added.
+pub(crate) async fn whitelist_add(
     data: &ProtobotData,
+    sender: &CommandSender,
     player_name: &str,
     category: PterodactylServerCategory,
-    name_and_uuid: OnceCell<(String, Uuid)>,
-) -> crate::Result<()> {
-    let Some(mut whitelist) = get_whitelist(data, category).await? else {
-        return Ok(());
+    name_and_uuid: &OnceCell<(String, Uuid)>,
+) -> crate::Result<bool> {
+    let Some(mut whitelist) = get_whitelist(data, sender, category).await? else {
+        return Ok(false);
     };
 
     if whitelist
         .iter()
         .any(|player| player.name.eq_ignore_ascii_case(player_name))
     {
-        error!("That player was already whitelisted");
-        return Ok(());
+        sender.error("That player was already whitelisted");
+        return Ok(false);
     }
 
     let (player_name, player_uuid) = name_and_uuid
-        .get_or_try_init(|| async {
-            let client = reqwest::Client::builder()
-                .user_agent(format!("protobot {}", git_version!()))
-                .build();
-            let client = match client {
-                Ok(client) => client,
-                Err(err) => return Err(err.into()),
-            };
-            let response = client
-                .post("https://api.minecraftservices.com/minecraft/profile/lookup/bulk/byname")
-                .header("Accept", "application/json")
-                .header("Content-Type", "application/json")
-                .json(&vec![player_name])
-                .send()
-                .await?;
-
-            if !response.status().is_success() {
-                let status = response.status();
-                error!(
-                    "Failed to request UUID: {}, {}",
-                    status,
-                    response.text().await?
-                );
-                return Err(crate::Error::Other("failed to request UUID".into()));
-            }
-
-            #[derive(Deserialize)]
-            struct MojangPlayer {
-                id: Uuid,
-                name: String,
-            }
-
-            let mojang_players = response.json::<Vec<MojangPlayer>>().await?;
-            if mojang_players.len() != 1 {
-                return Err(crate::Error::Other(
-                    "Mojang server didn't return 1 player when requested".to_owned(),
-                ));
-            }
-
-            let player = mojang_players.into_iter().next().unwrap();
-            Ok((player.name, player.id))
-        })
+        .get_or_try_init(|| lookup_player(player_name))
         .await?;
 
     whitelist.push(Player {
@@ -180,26 +207,24 @@ async fn whitelist_add(
     });
     whitelist.sort_by_key(|player| player.name.to_ascii_lowercase());
 
-    set_whitelist(data, whitelist, category, |server| {
+    set_whitelist(data, sender, whitelist, category, |server| {
         format!("Whitelisted {player_name} on {server}")
     })
     .await?;
     if category.should_be_opped() {
-        run_command(data, format!("op {player_name}"), category, |server| {
-            format!("Opped {player_name} on {server}")
-        })
-        .await?;
+        ops::op_whitelisted_player(data, sender, player_name, *player_uuid, category).await?;
     }
 
-    Ok(())
+    Ok(true)
 }
 
-async fn whitelist_remove(
+pub(crate) async fn whitelist_remove(
     data: &ProtobotData,
+    sender: &CommandSender,
     player_name: &str,
     category: PterodactylServerCategory,
 ) -> crate::Result<()> {
-    let Some(mut whitelist) = get_whitelist(data, category).await? else {
+    let Some(mut whitelist) = get_whitelist(data, sender, category).await? else {
         return Ok(());
     };
 
@@ -212,19 +237,21 @@ async fn whitelist_remove(
         !matches
     });
     if !removed {
-        error!("That player was not whitelisted");
+        remove_expiry(player_name, category).await;
+        role_whitelist::forget_grants(player_name, category).await;
+        sender.error("That player was not whitelisted");
         return Ok(());
     };
 
-    set_whitelist(data, whitelist, category, |server| {
+    set_whitelist(data, sender, whitelist, category, |server| {
         format!("Unwhitelisted {player_name} on {server}")
     })
     .await?;
+    // Only forgotten once the player is gone, so that a failed expiry is retried
+    remove_expiry(player_name, category).await;
+    role_whitelist::forget_grants(player_name, category).await;
     if category.should_be_opped() {
-        run_command(data, format!("deop {player_name}"), category, |server| {
-            format!("De-opped {player_name} on {server}")
-        })
-        .await?;
+        ops::deop_player(data, sender, player_name, category).await?;
     }
 
     Ok(())
@@ -232,46 +259,57 @@ async fn whitelist_remove(
 
 async fn whitelist_list(
     data: &ProtobotData,
+    sender: &CommandSender,
     category: PterodactylServerCategory,
 ) -> crate::Result<()> {
-    let Some(whitelist) = get_whitelist(data, category).await? else {
+    let Some(whitelist) = get_whitelist(data, sender, category).await? else {
         return Ok(());
     };
     let mut whitelist: Vec<_> = whitelist.into_iter().map(|player| player.name).collect();
     if whitelist.is_empty() {
-        info!("There are no players on the whitelist");
+        sender.info("There are no players on the whitelist");
     } else {
         whitelist.sort();
         let num_players = whitelist.len();
-        info!(
+        sender.info(format!(
             "There are {} players on the whitelist: {}",
             num_players,
             whitelist.join(", ")
-        );
+        ));
     }
     Ok(())
 }
 
+/// Returns the names of the players whitelisted on a category.
+pub(crate) async fn whitelisted_players(
+    data: &ProtobotData,
+    sender: &CommandSender,
+    category: PterodactylServerCategory,
+) -> crate::Result<Option<Vec<String>>> {
+    Ok(get_whitelist(data, sender, category)
+        .await?
+        .map(|whitelist| whitelist.into_iter().map(|player| player.name).collect()))
+}
+
 async fn get_whitelist(
     data: &ProtobotData,
+    sender: &CommandSender,
     category: PterodactylServerCategory,
 ) -> crate::Result<Option<Vec<Player>>> {
+    // Like `read_category_json`, but skipping the staff-only whitelist of servers in maintenance
     let config = config::get();
     let Some(server) = config.pterodactyl_servers(category).next() else {
-        error!("No servers of the given category");
+        sender.error("No servers of the given category");
         return Ok(None);
     };
-    let whitelist_json = data
-        .pterodactyl
-        .get_server(&server.id)
-        .file_contents_text("whitelist.json")
-        .await?;
-    let whitelist = serde_json::from_str(&whitelist_json)?;
-    Ok(Some(whitelist))
+    let ptero_server = data.pterodactyl.get_server(server)?;
+    let json = maintenance::read_whitelist(server, &ptero_server).await?;
+    Ok(Some(serde_json::from_str(&json)?))
 }
 
 async fn set_whitelist(
     data: &ProtobotData,
+    sender: &CommandSender,
     whitelist: Vec<Player>,
     category: PterodactylServerCategory,
     mut message: impl FnMut(&str) -> String,
@@ -282,12 +320,16 @@ async fn set_whitelist(
         let whitelist_json = whitelist_json.clone();
         let message = message(&server.name);
         async move {
-            let ptero_server = data.pterodactyl.get_server(&server.id);
+            if maintenance::set_saved_whitelist(&server.name, &whitelist_json).await {
+                sender.info(format!("{message} once maintenance ends"));
+                return Ok(());
+            }
+            let ptero_server = data.pterodactyl.get_server(server)?;
             ptero_server
                 .write_file("whitelist.json", whitelist_json)
                 .await?;
             send_command_safe(&ptero_server, "whitelist reload").await?;
-            info!("{}", message);
+            sender.info(message);
             Ok::<(), crate::Error>(())
         }
     });
@@ -295,33 +337,179 @@ async fn set_whitelist(
     Ok(())
 }
 
-async fn run_command(
-    data: &ProtobotData,
-    command: String,
-    category: PterodactylServerCategory,
-    mut message: impl FnMut(&str) -> String,
-) -> crate::Result<()> {
+/// Periodically removes players whose temporary whitelist entries have expired, and reminds staff
+/// about entries that are about to expire.
+pub(crate) async fn run_expiry_task(data: ProtobotData) {
+    loop {
+        tokio::select! {
+            _ = crate::wait_shutdown() => break,
+            _ = tokio::time::sleep(EXPIRY_CHECK_INTERVAL) => {}
+        }
+        if let Err(err) = check_expiries(&data).await {
+            error!("Failed to check whitelist expiries: {}", err);
+        }
+    }
+}
+
+async fn check_expiries(data: &ProtobotData) -> crate::Result<()> {
     let config = config::get();
-    let tasks = config.pterodactyl_servers(category).map(|server| {
-        let command = command.clone();
-        let message = message(&server.name);
-        async move {
-            let ptero_server = data.pterodactyl.get_server(&server.id);
-            send_command_safe(&ptero_server, command).await?;
-            info!("{}", message);
-            Ok::<(), crate::Error>(())
+    let now = Utc::now();
+    let (expired, to_remind): (Vec<_>, Vec<_>) = GuildStorage::get(config.guild_id)
+        .await
+        .whitelist_expiries
+        .iter()
+        .filter(|expiry| !expiry.reminded || expiry.expires_at() <= now)
+        .filter(|expiry| expiry.expires_at() - EXPIRY_REMINDER_TIME <= now)
+        .cloned()
+        .partition(|expiry| expiry.expires_at() <= now);
+
+    let sender = CommandSender::new("Whitelist expiry");
+    // Entries which fail to be removed or reminded about are retried on the next check
+    for expiry in expired {
+        if let Err(err) = whitelist_remove(data, &sender, &expiry.player, expiry.category).await {
+            error!(
+                "Failed to remove expired whitelist entry of {} on {:?}: {}",
+                expiry.player, expiry.category, err
+            );
         }
-    });
-    futures::future::try_join_all(tasks).await?;
+    }
+
+    for expiry in to_remind {
+        if let Some(staff_channel) = config.special_channels.staff {
+            if let Err(err) = staff_channel
+                .send_message(
+                    &data.discord_handle,
+                    CreateMessage::new().content(format!(
+                        "The temporary whitelist entry of {} on {:?} expires <t:{}:R>. Use `whitelist extend {} {} <duration>` to extend it.",
+                        expiry.player,
+                        expiry.category,
+                        expiry.expires_at,
+                        expiry.player,
+                        expiry.category.name(),
+                    )),
+                )
+                .await
+            {
+                error!(
+                    "Failed to remind about whitelist expiry of {}: {}",
+                    expiry.player, err
+                );
+                continue;
+            }
+        } else {
+            warn!(
+                "No staff channel to remind about whitelist expiry of {}",
+                expiry.player
+            );
+        }
+
+        let mut storage = GuildStorage::get_mut(config.guild_id).await;
+        for stored_expiry in &mut storage.whitelist_expiries {
+            if stored_expiry.matches(&expiry.player, expiry.category) {
+                stored_expiry.reminded = true;
+            }
+        }
+        storage.save().await;
+    }
+
     Ok(())
 }
 
-fn print_usage() {
-    info!("(whitelist add <player> <category|all> [uuid]) | (whitelist remove <player> <category|all>) | (whitelist list <category>)");
+pub(crate) async fn set_expiry(
+    player_name: &str,
+    category: PterodactylServerCategory,
+    expires_at: DateTime<Utc>,
+) {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    storage
+        .whitelist_expiries
+        .retain(|expiry| !expiry.matches(player_name, category));
+    storage.whitelist_expiries.push(WhitelistExpiry {
+        player: player_name.to_owned(),
+        category,
+        expires_at: expires_at.timestamp(),
+        reminded: false,
+    });
+    storage.save().await;
+}
+
+async fn extend_expiry(
+    player_name: &str,
+    category: PterodactylServerCategory,
+    duration: chrono::Duration,
+) -> Result<DateTime<Utc>, ExtendError> {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let Some(expiry) = storage
+        .whitelist_expiries
+        .iter_mut()
+        .find(|expiry| expiry.matches(player_name, category))
+    else {
+        storage.discard();
+        return Err(ExtendError::NotTemporary);
+    };
+    let Some(expires_at) = expiry
+        .expires_at()
+        .max(Utc::now())
+        .checked_add_signed(duration)
+    else {
+        storage.discard();
+        return Err(ExtendError::TooLong);
+    };
+    expiry.expires_at = expires_at.timestamp();
+    expiry.reminded = false;
+    storage.save().await;
+    Ok(expires_at)
+}
+
+enum ExtendError {
+    NotTemporary,
+    TooLong,
+}
+
+pub(crate) async fn remove_expiry(player_name: &str, category: PterodactylServerCategory) -> bool {
+    let mut storage = GuildStorage::get_mut(config::get().guild_id).await;
+    let num_expiries = storage.whitelist_expiries.len();
+    storage
+        .whitelist_expiries
+        .retain(|expiry| !expiry.matches(player_name, category));
+    if storage.whitelist_expiries.len() == num_expiries {
+        storage.discard();
+        false
+    } else {
+        storage.save().await;
+        true
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+fn print_usage(sender: &CommandSender) {
+    sender.info("(whitelist add <player> <category|all> [uuid]) | (whitelist temp <player> <category|all> <duration>) | (whitelist extend <player> <category|all> <duration>) | (whitelist permanent <player> <category|all>) | (whitelist remove <player> <category|all>) | (whitelist list <category>) | (whitelist expiries)");
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WhitelistExpiry {
+    player: String,
+    category: PterodactylServerCategory,
+    expires_at: i64,
+    #[serde(default)]
+    reminded: bool,
+}
+
+impl WhitelistExpiry {
+    fn expires_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.expires_at, 0).unwrap_or_default()
+    }
+
+    fn matches(&self, player_name: &str, category: PterodactylServerCategory) -> bool {
+        self.category == category && self.player.eq_ignore_ascii_case(player_name)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
-struct Player {
-    name: String,
-    uuid: Uuid,
+pub(crate) struct Player {
+    pub(crate) name: String,
+    pub(crate) uuid: Uuid,
 }
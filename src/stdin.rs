@@ -1,7 +1,11 @@
+use crate::command_sender::CommandSender;
 use crate::config;
-use crate::pterodactyl::{perms_sync, whitelist};
+use crate::pterodactyl::{
+    bans, console_rules, discovery, maintenance, ops, perms_sync, playtime, role_whitelist,
+    whitelist,
+};
 use crate::ProtobotData;
-use log::{error, info};
+use log::error;
 use std::io;
 use std::io::BufRead;
 
@@ -17,7 +21,13 @@ pub(crate) fn handle_stdin_loop(runtime: &tokio::runtime::Runtime, data: Protobo
                         if let Some(slash_removed) = line.strip_prefix('/') {
                             line = slash_removed;
                         }
-                        if let Err(err) = handle_command(&data, line.split_whitespace()).await {
+                        if let Err(err) = handle_command(
+                            &data,
+                            &CommandSender::console(),
+                            line.split_whitespace(),
+                        )
+                        .await
+                        {
                             error!("Error while handling stdin: {}", err);
                         }
                     });
@@ -32,46 +42,71 @@ pub(crate) fn handle_stdin_loop(runtime: &tokio::runtime::Runtime, data: Protobo
 
 macro_rules! declare_commands {
     ($(($name:literal, $func:path, $description:literal);)*) => {
-        pub async fn handle_command(data: &ProtobotData, mut args: impl Iterator<Item = &str>) -> crate::Result<()> {
+        pub async fn handle_command(data: &ProtobotData, sender: &CommandSender, mut args: impl Iterator<Item = &str>) -> crate::Result<()> {
             let Some(command) = args.next() else { return Ok(()); };
             match command {
                 $(
-                $name => $func(data, args).await,
+                $name => $func(data, sender, args).await,
                 )*
                 _ => {
-                    show_help();
+                    show_help(sender);
                     Ok(())
                 },
             }
         }
 
-        fn show_help() {
-            info!("ProtoBot console help");
+        fn show_help(sender: &CommandSender) {
+            sender.info("ProtoBot console help");
             $(
-            info!(concat!($name, ": ", $description));
+            sender.info(concat!($name, ": ", $description));
             )*
-            info!("help: displays this message.");
+            sender.info("help: displays this message.");
         }
     }
 }
 
 declare_commands! {
+    ("ban", bans::run, "manage server bans");
+    ("links", role_whitelist::run, "manage linked Minecraft accounts");
+    ("maintenance", maintenance::run, "locks servers down to staff for maintenance");
+    ("ops", ops::run, "manage server ops");
+    ("panel_emails", perms_sync::run_panel_emails, "manage registered panel emails");
     ("perms_sync", perms_sync::run, "synchronizes user permissions on a ptero server");
+    ("playtime", playtime::run, "reports inactive players");
     ("reload", reload_config, "reloads bot config");
+    ("rules", console_rules::run, "lists and tests console trigger rules");
+    ("servers", discovery::run, "lists servers on the panel and generates config entries");
     ("stop", stop, "stops the bot");
     ("whitelist", whitelist::run, "manage server whitelists");
 }
 
 async fn reload_config(
-    _data: &ProtobotData,
+    data: &ProtobotData,
+    sender: &CommandSender,
     _args: impl Iterator<Item = &str>,
 ) -> crate::Result<()> {
+    // Panel clients are only created on startup
+    let panels = |config: &config::Config| -> Vec<_> {
+        config
+            .pterodactyl_panels()
+            .map(|(name, panel)| (name.to_owned(), panel.domain, panel.api_key))
+            .collect()
+    };
+    let old_panels = panels(&config::get());
     config::reload()?;
-    info!("Reloaded config");
+    sender.info("Reloaded config");
+    if panels(&config::get()) != old_panels {
+        sender.error("Panel changes only take effect when the bot restarts");
+    }
+    discovery::lint_panel(data).await;
     Ok(())
 }
 
-async fn stop(_data: &ProtobotData, _args: impl Iterator<Item = &str>) -> crate::Result<()> {
+async fn stop(
+    _data: &ProtobotData,
+    _sender: &CommandSender,
+    _args: impl Iterator<Item = &str>,
+) -> crate::Result<()> {
     crate::shutdown();
     Ok(())
 }